@@ -1,4 +1,36 @@
+use std::fs;
+
 fn main() {
     // println!("cargo:rustc-link-lib=opencv4");
     println!("cargo:rustc-link-lib=mediagraph");
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-env-changed=SHADERC_LIB_DIR");
+
+    // the shader cache is keyed on the compiler version, see shader_cache::ShaderCache
+    println!("cargo:rustc-env=SHADERC_VERSION={}", shaderc_version());
+}
+
+/// The locked versions of shaderc and shaderc-sys, which bundles glslang, plus the directory of
+/// a system libshaderc if one is linked instead of the bundled build.
+fn shaderc_version() -> String {
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let version = |package: &str| {
+        let header = format!("name = \"{}\"\nversion = \"", package);
+        lock.split("[[package]]\n")
+            .find_map(|entry| entry.strip_prefix(&header))
+            .and_then(|rest| rest.split('"').next())
+            .unwrap_or("unknown")
+            .to_string()
+    };
+
+    let system_lib = std::env::var("SHADERC_LIB_DIR").unwrap_or_default();
+
+    format!(
+        "shaderc {} shaderc-sys {} {}",
+        version("shaderc"),
+        version("shaderc-sys"),
+        system_lib
+    )
 }
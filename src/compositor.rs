@@ -0,0 +1,427 @@
+use nannou::prelude::*;
+
+use crate::render::*;
+use crate::std140::*;
+use crate::uniforms::*;
+
+/// The name the layer shader includes the generated `Layer` uniform block by.
+pub const GLSL_INCLUDE_NAME: &str = "layer.glsl";
+
+/// The most layers a compositor can hold, which sizes its uniform arena.
+pub const MAX_LAYERS: usize = 32;
+
+/// Room for one layer block at the largest offset alignment wgpu allows.
+const LAYER_SLOT_SIZE: u64 = 256;
+
+/// Where a layer sits in the output, in texture coordinates with y down.
+/// Scale and rotation are applied around the center of the layer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerTransform {
+    pub translation: Vec2,
+    pub scale: Vec2,
+    /// Clockwise, in radians.
+    pub rotation: f32,
+}
+
+impl Default for LayerTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vec2::ZERO,
+            scale: Vec2::ONE,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl LayerTransform {
+    /// The matrix taking output texture coordinates to layer texture coordinates.
+    /// Rotation happens in a space scaled by the output's aspect ratio, so layers don't shear.
+    fn inverse_matrix(&self, aspect: f32) -> Mat4 {
+        let center = vec3(0.5, 0.5, 0.0);
+        let to_square = Mat4::from_scale(vec3(aspect, 1.0, 1.0));
+
+        let forward = Mat4::from_translation(center + self.translation.extend(0.0))
+            * to_square.inverse()
+            * Mat4::from_rotation_z(self.rotation)
+            * to_square
+            * Mat4::from_scale(self.scale.extend(1.0))
+            * Mat4::from_translation(-center);
+
+        forward.inverse()
+    }
+}
+
+/// The uniforms of `layer.frag`.
+struct LayerUniforms {
+    transform: [[f32; 4]; 4],
+    opacity: f32,
+    /// Whether opacity scales the whole colour rather than only its alpha: for the modes that
+    /// blend premultiplied colour, and for `Replace`, which fades to transparent black.
+    premultiplied: bool,
+    /// Whether the layer's texture has straight alpha that the shader must premultiply.
+    premultiply: bool,
+}
+
+impl UniformBlock for LayerUniforms {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder("Layer")
+            .field("layer_transform", UniformType::Mat4)
+            .field("layer_opacity", UniformType::Float)
+            .field("layer_premultiplied", UniformType::Uint)
+            .field("layer_premultiply", UniformType::Uint)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer
+            .write(self.transform)
+            .write(self.opacity)
+            .write(self.premultiplied)
+            .write(self.premultiply);
+    }
+}
+
+/// The contents of the `layer.glsl` include, generated from the Rust definition of the layer uniforms.
+/// Define LAYER_BINDING before including it if the block is not at binding 2.
+pub fn glsl_include() -> String {
+    let mut glsl = String::from(
+        "// Generated from compositor::LayerUniforms, do not edit.
+
+#ifndef LAYER_GLSL
+#define LAYER_GLSL
+
+#ifndef LAYER_BINDING
+#define LAYER_BINDING 2
+#endif
+
+",
+    );
+
+    glsl.push_str(&LayerUniforms::layout().glsl_block(0, "LAYER_BINDING"));
+    glsl.push_str("\n#endif\n");
+    glsl
+}
+
+/// A texture drawn into by a nannou `Draw` every frame, e.g. for vector overlays and widgets.
+struct DrawTarget {
+    texture: wgpu::Texture,
+    renderer: nannou::draw::Renderer,
+}
+
+/// One layer of a compositor.
+pub struct Layer {
+    pub name: String,
+    pub opacity: f32,
+    pub visible: bool,
+    pub transform: LayerTransform,
+    renderer: CustomRenderer,
+    slot: UniformSlot,
+    draw_target: Option<DrawTarget>,
+}
+
+impl Layer {
+    pub fn blend_mode(&self) -> BlendMode {
+        self.renderer.blend_mode()
+    }
+}
+
+/// Blends an ordered stack of layers into a single output texture.
+///
+/// Layers are sampled by `layer.frag` with the following layout:
+/// - binding 0: the layer's texture
+/// - binding 1: the sampler
+/// - binding 2: the layer's uniform block, see `glsl_include`
+///
+/// The first layer is at the bottom. Opacity, visibility and transforms can be changed freely
+/// and take effect on the next `update`; blend modes rebuild the layer's pipeline.
+pub struct Compositor {
+    arena: UniformArena,
+    device_sample_count: u32,
+    fs_mod: wgpu::ShaderModule,
+    pub layers: Vec<Layer>,
+    pub output_texture: wgpu::Texture,
+    sampler: wgpu::Sampler,
+    pub texture_reshaper: wgpu::TextureReshaper,
+    vs_mod: wgpu::ShaderModule,
+    width: u32,
+    height: u32,
+}
+
+impl Compositor {
+    pub fn new(
+        device: &wgpu::Device,
+        vs_mod: wgpu::ShaderModule,
+        fs_mod: wgpu::ShaderModule,
+        width: u32,
+        height: u32,
+        device_sample_count: u32,
+    ) -> Self {
+        let output_texture = create_app_texture(device, width, height, 1);
+        let texture_reshaper =
+            create_texture_reshaper(device, &output_texture, 1, device_sample_count);
+
+        // layers outside their bounds are transparent rather than repeating their edges
+        let sampler = wgpu::SamplerBuilder::new()
+            .address_mode(wgpu::AddressMode::ClampToEdge)
+            .build(device);
+
+        Self {
+            arena: UniformArena::new(device, MAX_LAYERS as u64 * LAYER_SLOT_SIZE),
+            device_sample_count,
+            fs_mod,
+            layers: vec![],
+            output_texture,
+            sampler,
+            texture_reshaper,
+            vs_mod,
+            width,
+            height,
+        }
+    }
+
+    /// Adds a layer showing a texture on top of the stack, e.g. the camera video or an effect output.
+    pub fn add_texture_layer(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        texture: &wgpu::Texture,
+        blend_mode: BlendMode,
+    ) -> Result<(), RendererError> {
+        self.push_layer(device, name, texture, blend_mode)?;
+        Ok(())
+    }
+
+    /// Adds a layer on top of the stack that is drawn with `draw`.
+    /// Draw output has premultiplied alpha, so these layers should use one of the modes that
+    /// expect it: `Premultiplied`, `Multiply` or `Screen`.
+    pub fn add_draw_layer(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        blend_mode: BlendMode,
+    ) -> Result<(), RendererError> {
+        let texture = create_app_texture(device, self.width, self.height, 1);
+        let renderer = nannou::draw::RendererBuilder::new()
+            .build_from_texture_descriptor(device, texture.descriptor());
+
+        let layer = self.push_layer(device, name, &texture, blend_mode)?;
+        layer.draw_target = Some(DrawTarget { texture, renderer });
+        Ok(())
+    }
+
+    fn push_layer(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        texture: &wgpu::Texture,
+        blend_mode: BlendMode,
+    ) -> Result<&mut Layer, RendererError> {
+        if self.layers.len() == MAX_LAYERS {
+            return Err(RendererError::TooManyLayers(MAX_LAYERS));
+        }
+
+        println!("creating layer {}", name);
+
+        let slot = self.arena.allocate(&LayerUniforms::layout());
+        let renderer = self.create_layer_renderer(device, texture, slot, blend_mode)?;
+
+        self.layers.push(Layer {
+            name: name.to_string(),
+            opacity: 1.0,
+            visible: true,
+            transform: LayerTransform::default(),
+            renderer,
+            slot,
+            draw_target: None,
+        });
+
+        Ok(self.layers.last_mut().unwrap())
+    }
+
+    fn create_layer_renderer(
+        &self,
+        device: &wgpu::Device,
+        texture: &wgpu::Texture,
+        slot: UniformSlot,
+        blend_mode: BlendMode,
+    ) -> Result<CustomRenderer, RendererError> {
+        CustomRenderer::new(
+            device,
+            &self.vs_mod,
+            &self.fs_mod,
+            None,
+            None,
+            Some(&vec![texture]),
+            Some(&self.sampler),
+            Some(self.arena.binding(slot)),
+            blend_mode,
+            RenderOutput::External,
+            self.width,
+            self.height,
+            1,
+            self.device_sample_count,
+        )
+    }
+
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    /// Moves a layer to a new position in the stack, 0 being the bottom.
+    pub fn move_layer(&mut self, name: &str, index: usize) {
+        if let Some(i) = self.layers.iter().position(|l| l.name == name) {
+            let layer = self.layers.remove(i);
+            let index = index.min(self.layers.len());
+            self.layers.insert(index, layer);
+        }
+    }
+
+    pub fn set_blend_mode(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        blend_mode: BlendMode,
+    ) -> Result<(), RendererError> {
+        let (vs_mod, fs_mod) = (&self.vs_mod, &self.fs_mod);
+        match self.layers.iter_mut().find(|l| l.name == name) {
+            Some(layer) => layer
+                .renderer
+                .set_blend_mode(device, vs_mod, fs_mod, blend_mode),
+            None => Ok(()),
+        }
+    }
+
+    /// Points a texture layer at a different texture, e.g. after an effect chain was resized.
+    pub fn set_layer_texture(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        texture: &wgpu::Texture,
+    ) -> Result<(), RendererError> {
+        let (slot, blend_mode) = match self.layer(name) {
+            Some(layer) => (layer.slot, layer.blend_mode()),
+            None => return Ok(()),
+        };
+
+        let renderer = self.create_layer_renderer(device, texture, slot, blend_mode)?;
+        if let Some(layer) = self.layer_mut(name) {
+            layer.renderer = renderer;
+            layer.draw_target = None;
+        }
+
+        Ok(())
+    }
+
+    /// Resizes the output, e.g. when the window is resized. Draw layers get new textures of the
+    /// new size; texture layers keep theirs and are stretched to fit.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> Result<(), RendererError> {
+        if (width, height) == (self.width, self.height) {
+            return Ok(());
+        }
+
+        self.width = width;
+        self.height = height;
+        self.output_texture = create_app_texture(device, width, height, 1);
+        self.texture_reshaper =
+            create_texture_reshaper(device, &self.output_texture, 1, self.device_sample_count);
+
+        for i in 0..self.layers.len() {
+            if self.layers[i].draw_target.is_none() {
+                continue;
+            }
+
+            let texture = create_app_texture(device, width, height, 1);
+            let draw_renderer = nannou::draw::RendererBuilder::new()
+                .build_from_texture_descriptor(device, texture.descriptor());
+            let layer = &self.layers[i];
+            let renderer =
+                self.create_layer_renderer(device, &texture, layer.slot, layer.blend_mode())?;
+
+            let layer = &mut self.layers[i];
+            layer.renderer = renderer;
+            layer.draw_target = Some(DrawTarget {
+                texture,
+                renderer: draw_renderer,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Renders `draw` into a draw layer, replacing what was drawn into it before.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        name: &str,
+        draw: &Draw,
+    ) {
+        let target = match self.layer_mut(name).and_then(|l| l.draw_target.as_mut()) {
+            Some(target) => target,
+            None => return,
+        };
+
+        target
+            .renderer
+            .render_to_texture(device, encoder, draw, &target.texture);
+    }
+
+    /// Uploads the opacity and transform of every layer.
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        let aspect = self.width as f32 / self.height as f32;
+
+        for layer in self.layers.iter() {
+            let blend_mode = layer.blend_mode();
+            // texture layers have straight alpha unless they're blended as premultiplied
+            let straight = layer.draw_target.is_none() && blend_mode != BlendMode::Premultiplied;
+
+            let uniforms = LayerUniforms {
+                transform: layer.transform.inverse_matrix(aspect).to_cols_array_2d(),
+                opacity: layer.opacity,
+                premultiplied: blend_mode.expects_premultiplied()
+                    || blend_mode == BlendMode::Replace,
+                premultiply: straight && blend_mode.expects_premultiplied(),
+            };
+            self.arena.set(layer.slot, &uniforms);
+        }
+
+        self.arena.update(queue);
+    }
+
+    /// Clears the output and blends every visible layer into it, bottom first.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let texture_view = self.output_texture.view().build();
+
+        wgpu::RenderPassBuilder::new()
+            .color_attachment(&texture_view, |color| {
+                color.load_op(wgpu::LoadOp::Clear(wgpu::Color::BLACK))
+            })
+            .begin(encoder);
+
+        for layer in self.layers.iter().filter(|l| l.visible && l.opacity > 0.0) {
+            layer
+                .renderer
+                .render_to(encoder, &texture_view, wgpu::LoadOp::Load)
+                .expect("compositor layers are single sampled");
+        }
+    }
+
+    /// Draws the composited output to the given texture view.
+    pub fn present(
+        &self,
+        texture_view: &wgpu::TextureViewHandle,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.texture_reshaper
+            .encode_render_pass(texture_view, encoder);
+    }
+}
@@ -0,0 +1,111 @@
+use nannou::prelude::*;
+
+use crate::reflect::*;
+use crate::render::*;
+
+/// The workgroup size of effect compute shaders, see `shaders/lib/compute.glsl`.
+pub const WORKGROUP_SIZE: u32 = 8;
+
+/// The name compute shaders declare their output storage texture with.
+pub const OUTPUT_IMAGE_NAME: &str = "output_image";
+
+pub struct ComputeRenderer {
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: wgpu::PipelineLayout,
+    compute_pipeline: wgpu::ComputePipeline,
+    width: u32,
+    height: u32,
+    pub output_texture: wgpu::Texture,
+    pub texture_reshaper: wgpu::TextureReshaper,
+}
+
+/// A compute pipeline generator for a shader that writes one invocation per pixel of a storage texture.
+/// Resources are bound by the names the shader declares them with, and the output storage texture
+/// is bound as `OUTPUT_IMAGE_NAME`.
+impl ComputeRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        cs: &EffectShader,
+        resources: &NamedResources,
+        width: u32,
+        height: u32,
+        device_sample_count: u32,
+    ) -> Result<Self, RendererError> {
+        let output_texture = create_storage_texture(device, width, height);
+
+        println!("creating compute bind group");
+        let resources = resources
+            .clone()
+            .texture(OUTPUT_IMAGE_NAME, &output_texture);
+        let (bind_group_layout, bind_group) = create_bind_group(
+            device,
+            &cs.bindings,
+            &resources,
+            wgpu::ShaderStages::COMPUTE,
+        )?;
+
+        println!("creating compute pipeline");
+        let pipeline_layout = create_pipeline_layout(device, &bind_group_layout);
+        let compute_pipeline = create_compute_pipeline(device, &pipeline_layout, &cs.module);
+
+        let texture_reshaper =
+            create_texture_reshaper(device, &output_texture, 1, device_sample_count);
+
+        Ok(Self {
+            bind_group,
+            pipeline_layout,
+            compute_pipeline,
+            width,
+            height,
+            output_texture,
+            texture_reshaper,
+        })
+    }
+
+    /// Rebuilds the compute pipeline with a new shader module, keeping the existing bindings.
+    pub fn rebuild_pipeline(&mut self, device: &wgpu::Device, cs_mod: &wgpu::ShaderModule) {
+        self.compute_pipeline = create_compute_pipeline(device, &self.pipeline_layout, cs_mod);
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("compute-pass"),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch(
+            (self.width + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+            (self.height + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+            1,
+        );
+    }
+}
+
+/// A texture that compute shaders can write, later passes can sample or copy from,
+/// and blended fragment passes can draw over.
+pub fn create_storage_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    wgpu::TextureBuilder::new()
+        .size([width, height])
+        .usage(
+            wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+        )
+        .format(Frame::TEXTURE_FORMAT)
+        .build(device)
+}
+
+pub fn create_compute_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    cs_mod: &wgpu::ShaderModule,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("compute-pipeline"),
+        layout: Some(layout),
+        module: cs_mod,
+        entry_point: "main",
+    })
+}
@@ -0,0 +1,214 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use opencv::prelude::*;
+
+use crate::video_capture::FrameTag;
+
+/// A model run on camera frames by a `DetectorWorker`, e.g. a mediapipe solution.
+pub trait Detector: 'static {
+    type Output: Send + 'static;
+
+    /// Processes an RGB frame that is mirrored horizontally, like the displayed video.
+    fn process(&mut self, frame: &Mat) -> Self::Output;
+}
+
+/// A detector's output along with the tag of the frame it was computed from.
+pub struct DetectorResult<T> {
+    pub tag: FrameTag,
+    pub output: T,
+}
+
+/// How far a result lags behind the video when it arrives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DetectorLatency {
+    /// Frames captured since the one the result was computed from.
+    pub frames: u64,
+    /// Time from capturing the frame to receiving the result.
+    pub duration: Duration,
+}
+
+/// How many frames a `DetectorWorker` has processed, and how many it skipped because a newer
+/// frame arrived before it was ready for them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DetectorStats {
+    pub processed: u64,
+    pub skipped: u64,
+    /// The latency of the most recent result.
+    pub latency: Option<DetectorLatency>,
+}
+
+/// Runs a detector on its own thread.
+///
+/// Frames are sent as captured (BGR, unmirrored) and converted on the worker thread before
+/// they're processed. The detector is created on the worker thread too, so it doesn't have to
+/// be `Send`. Only the latest frame is kept: when inference is slower than capture, frames sent
+/// while the detector is busy replace each other rather than queueing up. Dropping the worker
+/// stops the thread once it has finished the current frame.
+pub struct DetectorWorker<D: Detector> {
+    mailbox: Arc<Mailbox<(FrameTag, Mat)>>,
+    response_receiver: Receiver<DetectorResult<D::Output>>,
+    processed: Arc<AtomicU64>,
+    skipped: AtomicU64,
+    last_sent: Cell<Option<FrameTag>>,
+    latency: Cell<Option<DetectorLatency>>,
+    worker_thread: Option<thread::JoinHandle<()>>,
+    detector: PhantomData<fn() -> D>,
+}
+
+impl<D: Detector> DetectorWorker<D> {
+    pub fn new<F>(name: &str, create: F) -> Self
+    where
+        F: FnOnce() -> D + Send + 'static,
+    {
+        let mailbox = Arc::new(Mailbox::new());
+        let (response_sender, response_receiver) = channel::<DetectorResult<D::Output>>();
+        let processed = Arc::new(AtomicU64::new(0));
+
+        let worker_mailbox = mailbox.clone();
+        let worker_processed = processed.clone();
+
+        let worker_thread = thread::Builder::new()
+            .name(format!("{} detector", name))
+            .spawn(move || {
+                let mut detector = create();
+
+                let mut rgb_frame = Mat::default();
+                let mut flip_frame = Mat::default();
+
+                while let Some((tag, frame)) = worker_mailbox.take() {
+                    if let Err(e) = preprocess(&frame, &mut rgb_frame, &mut flip_frame) {
+                        println!("unable to prepare frame for detection: {}", e);
+                        continue;
+                    }
+
+                    let output = detector.process(&flip_frame);
+                    worker_processed.fetch_add(1, Ordering::Relaxed);
+
+                    if response_sender
+                        .send(DetectorResult { tag, output })
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn detector thread");
+
+        Self {
+            mailbox,
+            response_receiver,
+            processed,
+            skipped: AtomicU64::new(0),
+            last_sent: Cell::new(None),
+            latency: Cell::new(None),
+            worker_thread: Some(worker_thread),
+            detector: PhantomData,
+        }
+    }
+
+    /// Hands a frame to the detector, replacing the previous one if it hasn't been taken yet.
+    pub fn send(&self, tag: FrameTag, frame: &Mat) {
+        self.last_sent.set(Some(tag));
+        if self.mailbox.post((tag, frame.clone())) {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The most recent result, if any have arrived since the last call.
+    pub fn try_recv(&self) -> Option<DetectorResult<D::Output>> {
+        let result = self.response_receiver.try_iter().last()?;
+
+        let latest = self
+            .last_sent
+            .get()
+            .map_or(result.tag.sequence, |t| t.sequence);
+        self.latency.set(Some(DetectorLatency {
+            frames: latest.saturating_sub(result.tag.sequence),
+            duration: Instant::now().duration_since(result.tag.captured),
+        }));
+
+        Some(result)
+    }
+
+    pub fn stats(&self) -> DetectorStats {
+        DetectorStats {
+            processed: self.processed.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            latency: self.latency.get(),
+        }
+    }
+}
+
+impl<D: Detector> Drop for DetectorWorker<D> {
+    fn drop(&mut self) {
+        // wakes the worker if it's waiting for a frame, and drops any frame it hasn't taken
+        self.mailbox.close();
+
+        if let Some(worker_thread) = self.worker_thread.take() {
+            let _ = worker_thread.join();
+        }
+    }
+}
+
+/// A single-slot mailbox where the latest item wins.
+struct Mailbox<T> {
+    slot: Mutex<MailboxSlot<T>>,
+    ready: Condvar,
+}
+
+struct MailboxSlot<T> {
+    item: Option<T>,
+    closed: bool,
+}
+
+impl<T> Mailbox<T> {
+    fn new() -> Self {
+        Self {
+            slot: Mutex::new(MailboxSlot {
+                item: None,
+                closed: false,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+
+    /// Stores `item`, returning whether it replaced one that was never taken.
+    fn post(&self, item: T) -> bool {
+        let mut slot = self.slot.lock().unwrap();
+        let replaced = slot.item.replace(item).is_some();
+        self.ready.notify_one();
+        replaced
+    }
+
+    /// Waits for an item, or returns `None` once the mailbox is closed.
+    fn take(&self) -> Option<T> {
+        let mut slot = self.slot.lock().unwrap();
+        loop {
+            if slot.closed {
+                return None;
+            }
+            if let Some(item) = slot.item.take() {
+                return Some(item);
+            }
+            slot = self.ready.wait(slot).unwrap();
+        }
+    }
+
+    fn close(&self) {
+        let mut slot = self.slot.lock().unwrap();
+        slot.closed = true;
+        slot.item = None;
+        self.ready.notify_all();
+    }
+}
+
+fn preprocess(frame: &Mat, rgb_frame: &mut Mat, flip_frame: &mut Mat) -> opencv::Result<()> {
+    opencv::imgproc::cvt_color(frame, rgb_frame, opencv::imgproc::COLOR_BGR2RGB, 0)?;
+    opencv::core::flip(rgb_frame, flip_frame, 1) // horizontal
+}
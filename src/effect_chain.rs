@@ -0,0 +1,624 @@
+use std::rc::Rc;
+
+use nannou::prelude::*;
+
+use crate::compute::*;
+use crate::landmarks::*;
+use crate::reflect::*;
+use crate::render::*;
+use crate::std140::UniformBlock;
+use crate::uniforms::Uniforms;
+
+/// The most previous frames a pass can sample.
+pub const MAX_HISTORY_FRAMES: usize = 4;
+
+// The names effect shaders declare the chain's resources with, see `shaders/lib/effect.glsl`.
+pub const PREVIOUS_PASS_NAME: &str = "tex";
+pub const SOURCE_NAME: &str = "video";
+pub const SAMPLER_NAME: &str = "tex_sampler";
+
+/// The name of a pass's history texture, `previous_frame` for the most recent frame
+/// and `previous_frame_2` and so on for older ones.
+pub fn history_name(index: usize) -> String {
+    match index {
+        0 => "previous_frame".to_string(),
+        i => format!("previous_frame_{}", i + 1),
+    }
+}
+
+/// How the chain provides a resource every pass can declare, or None for names that have to be
+/// provided as a named input or storage buffer.
+pub fn builtin_binding_kind(name: &str) -> Option<BindingKind> {
+    let is_history = (0..MAX_HISTORY_FRAMES).any(|i| history_name(i) == name);
+
+    if name == PREVIOUS_PASS_NAME || name == SOURCE_NAME || is_history {
+        Some(BindingKind::Texture)
+    } else if name == SAMPLER_NAME {
+        Some(BindingKind::Sampler { comparison: false })
+    } else if name == LANDMARKS_BLOCK_NAME {
+        Some(BindingKind::StorageBuffer { read_only: true })
+    } else if name == Uniforms::layout().name {
+        Some(BindingKind::UniformBuffer)
+    } else if name == OUTPUT_IMAGE_NAME {
+        Some(BindingKind::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        })
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EffectPassKind {
+    Fragment,
+    Compute,
+}
+
+impl EffectPassKind {
+    /// The stage a pass's shader must have as its `main` entry point.
+    pub fn stage(&self) -> naga::ShaderStage {
+        match self {
+            EffectPassKind::Fragment => naga::ShaderStage::Fragment,
+            EffectPassKind::Compute => naga::ShaderStage::Compute,
+        }
+    }
+}
+
+/// The kind of pass and macro definitions a shader is used with. Hot reload recompiles each
+/// variant a chain uses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderVariant {
+    pub kind: EffectPassKind,
+    pub defines: Vec<(String, Option<String>)>,
+}
+
+/// Describes a pass to add to an effect chain.
+pub struct EffectPassDescriptor {
+    pub name: String,
+    pub shader: EffectShader,
+    pub kind: EffectPassKind,
+    /// How many of its own previous outputs the pass can sample, for trails, echoes and simulations.
+    pub history_frames: usize,
+    /// Anything other than `Replace` draws the pass over the previous pass's output instead of
+    /// into a texture of its own, e.g. for sparkles or frames over the video. Since a pass can't
+    /// sample its own target, the previous output binding holds the source texture instead.
+    /// Passes blended with `Premultiplied`, `Multiply` or `Screen` must output premultiplied alpha.
+    pub blend_mode: BlendMode,
+    /// The macro definitions the shader was compiled with, so hot reload recompiles the same
+    /// variant of it.
+    pub defines: Vec<(String, Option<String>)>,
+}
+
+impl EffectPassDescriptor {
+    pub fn new(name: &str, shader: EffectShader) -> Self {
+        Self {
+            name: name.to_string(),
+            shader,
+            kind: EffectPassKind::Fragment,
+            history_frames: 0,
+            blend_mode: BlendMode::Replace,
+            defines: vec![],
+        }
+    }
+
+    pub fn compute(name: &str, shader: EffectShader) -> Self {
+        Self {
+            kind: EffectPassKind::Compute,
+            ..Self::new(name, shader)
+        }
+    }
+
+    pub fn history_frames(mut self, history_frames: usize) -> Self {
+        self.history_frames = history_frames;
+        self
+    }
+
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn defines(mut self, defines: &[(&str, Option<&str>)]) -> Self {
+        self.defines = defines
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.map(str::to_string)))
+            .collect();
+        self
+    }
+}
+
+/// The parts of a descriptor a pass keeps so it can be rebuilt.
+#[derive(Clone)]
+struct PassConfig {
+    name: String,
+    kind: EffectPassKind,
+    shader: Rc<EffectShader>,
+    history_frames: usize,
+    blend_mode: BlendMode,
+    defines: Vec<(String, Option<String>)>,
+}
+
+impl From<EffectPassDescriptor> for PassConfig {
+    fn from(descriptor: EffectPassDescriptor) -> Self {
+        Self {
+            name: descriptor.name,
+            kind: descriptor.kind,
+            shader: Rc::new(descriptor.shader),
+            history_frames: descriptor.history_frames,
+            blend_mode: descriptor.blend_mode,
+            defines: descriptor.defines,
+        }
+    }
+}
+
+/// The textures and buffers available to every pass of an effect chain.
+pub struct EffectResources<'a> {
+    pub source: &'a wgpu::Texture,
+    pub inputs: Vec<(&'a str, &'a wgpu::Texture)>,
+    pub landmarks: &'a LandmarkBuffer,
+    /// Storage buffers for state that outlives a frame, e.g. particles.
+    pub storage_buffers: Vec<(&'a str, &'a wgpu::Buffer, wgpu::BufferAddress)>,
+    /// Uniform blocks besides `Uniforms`, e.g. the `IsfInputs` of an imported ISF effect.
+    pub uniform_buffers: Vec<(&'a str, wgpu::BufferBinding<'a>)>,
+    pub sampler: &'a wgpu::Sampler,
+    pub uniforms: Option<wgpu::BufferBinding<'a>>,
+}
+
+pub enum PassRenderer {
+    Fragment(CustomRenderer),
+    Compute(ComputeRenderer),
+}
+
+impl PassRenderer {
+    /// The pass's own output. Blended passes draw into another pass's output and have none,
+    /// which `output_of` accounts for.
+    /// The size every pass renders at, in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn output_texture(&self) -> &wgpu::Texture {
+        match self {
+            PassRenderer::Fragment(renderer) => renderer
+                .output_texture
+                .as_ref()
+                .expect("blended passes have no output texture"),
+            PassRenderer::Compute(renderer) => &renderer.output_texture,
+        }
+    }
+
+    pub fn texture_reshaper(&self) -> &wgpu::TextureReshaper {
+        match self {
+            PassRenderer::Fragment(renderer) => renderer
+                .texture_reshaper
+                .as_ref()
+                .expect("blended passes have no output texture"),
+            PassRenderer::Compute(renderer) => &renderer.texture_reshaper,
+        }
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        match self {
+            PassRenderer::Fragment(renderer) => renderer.render(encoder),
+            PassRenderer::Compute(renderer) => renderer.render(encoder),
+        }
+    }
+}
+
+/// A single fragment or compute shader stage of an effect chain.
+pub struct EffectPass {
+    pub name: String,
+    pub renderer: PassRenderer,
+    config: PassConfig,
+    /// Copies of the pass's previous outputs, most recent first.
+    history: Vec<wgpu::Texture>,
+    /// The index of the pass whose output this pass blends into, if it doesn't render into its own.
+    target: Option<usize>,
+}
+
+impl EffectPass {
+    /// Shifts the history back one frame and copies the latest output into the front.
+    /// The output texture itself is never bound as an input, so the bind groups of this pass
+    /// and the ones after it stay valid from frame to frame.
+    fn update_history(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::Texture) {
+        for i in (1..self.history.len()).rev() {
+            copy_texture(encoder, &self.history[i - 1], &self.history[i]);
+        }
+
+        if let Some(latest) = self.history.first() {
+            copy_texture(encoder, output, latest);
+        }
+    }
+
+    fn clear_history(&self, encoder: &mut wgpu::CommandEncoder) {
+        for texture in self.history.iter() {
+            let texture_view = texture.view().build();
+            wgpu::RenderPassBuilder::new()
+                .color_attachment(&texture_view, |color| {
+                    color.load_op(wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT))
+                })
+                .begin(encoder);
+        }
+    }
+}
+
+/// Renders an ordered list of fragment and compute shaders, each one sampling the output of the pass before it.
+///
+/// Passes bind resources by the names their shaders declare them with, at whatever binding
+/// the shader chooses:
+/// - `Landmarks`: the landmark storage buffer
+/// - `tex`: the previous pass's output (the source texture for the first pass and blended passes)
+/// - `video`: the source texture
+/// - each named input texture, storage buffer and uniform buffer, by its name
+/// - `previous_frame`, `previous_frame_2`, ...: the pass's own previous outputs, most recent first
+/// - `tex_sampler`: the sampler
+/// - `Uniforms`: the uniform buffer, if one was given
+/// - `output_image`: a compute pass's output storage texture
+///
+/// A shader declaring a resource the chain doesn't have fails with `RendererError::MissingResource`.
+///
+/// A pass with a blend mode other than `Replace` is drawn over the output of the pass before it,
+/// and the passes after it see the blended result.
+pub struct EffectChain {
+    device_sample_count: u32,
+    pub passes: Vec<EffectPass>,
+    vs_mod: wgpu::ShaderModule,
+    width: u32,
+    height: u32,
+}
+
+impl EffectChain {
+    pub fn new(
+        device: &wgpu::Device,
+        vs_mod: wgpu::ShaderModule,
+        descriptors: Vec<EffectPassDescriptor>,
+        resources: &EffectResources,
+        width: u32,
+        height: u32,
+        device_sample_count: u32,
+    ) -> Result<Self, RendererError> {
+        if descriptors.is_empty() {
+            return Err(RendererError::EmptyEffectChain);
+        }
+
+        let passes = descriptors.into_iter().map(PassConfig::from).collect();
+
+        let mut chain = Self {
+            device_sample_count,
+            passes: vec![],
+            vs_mod,
+            width,
+            height,
+        };
+
+        chain.build_passes(device, passes, resources)?;
+
+        Ok(chain)
+    }
+
+    fn build_passes(
+        &mut self,
+        device: &wgpu::Device,
+        passes: Vec<PassConfig>,
+        resources: &EffectResources,
+    ) -> Result<(), RendererError> {
+        let mut shared = NamedResources::new()
+            .buffer(
+                LANDMARKS_BLOCK_NAME,
+                wgpu::BufferBinding {
+                    buffer: &resources.landmarks.buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(LANDMARK_BUFFER_SIZE),
+                },
+            )
+            .texture(SOURCE_NAME, resources.source)
+            .sampler(SAMPLER_NAME, resources.sampler);
+
+        for (name, texture) in resources.inputs.iter() {
+            shared = shared.texture(name, texture);
+        }
+
+        for (name, buffer, size) in resources.storage_buffers.iter() {
+            shared = shared.sized_buffer(name, buffer, *size);
+        }
+
+        for (name, binding) in resources.uniform_buffers.iter() {
+            shared = shared.buffer(name, binding.clone());
+        }
+
+        if let Some(uniforms) = resources.uniforms.as_ref() {
+            shared = shared.uniform_block(uniforms.clone(), &Uniforms::layout());
+        }
+
+        let mut built: Vec<EffectPass> = vec![];
+
+        for config in passes {
+            println!("creating effect pass {}", config.name);
+
+            let pass_error = |source| RendererError::Pass {
+                name: config.name.clone(),
+                source: Box::new(source),
+            };
+
+            if config.history_frames > MAX_HISTORY_FRAMES {
+                return Err(pass_error(RendererError::TooManyHistoryFrames(
+                    config.history_frames,
+                )));
+            }
+
+            let blended = config.blend_mode != BlendMode::Replace;
+            if blended && config.kind == EffectPassKind::Compute {
+                return Err(pass_error(RendererError::BlendedComputePass));
+            }
+
+            // blended passes draw into the target of the pass before them
+            let target = match built.len() {
+                0 => None,
+                i if blended => Some(built[i - 1].target.unwrap_or(i - 1)),
+                _ => None,
+            };
+
+            let history: Vec<wgpu::Texture> = (0..config.history_frames)
+                .map(|_| create_app_texture(device, self.width, self.height, 1))
+                .collect();
+
+            let renderer = {
+                let previous = match built.last() {
+                    Some(_) if target.is_some() => resources.source,
+                    Some(pass) => output_of(&built, pass),
+                    None => resources.source,
+                };
+
+                let mut named = shared.clone().texture(PREVIOUS_PASS_NAME, previous);
+                for (i, texture) in history.iter().enumerate() {
+                    named = named.texture(&history_name(i), texture);
+                }
+
+                let renderer = match config.kind {
+                    EffectPassKind::Fragment => CustomRenderer::with_bindings(
+                        device,
+                        &self.vs_mod,
+                        &config.shader,
+                        &named,
+                        config.blend_mode,
+                        match target {
+                            Some(_) => RenderOutput::External,
+                            None => RenderOutput::Owned,
+                        },
+                        self.width,
+                        self.height,
+                        1,
+                        self.device_sample_count,
+                    )
+                    .map(PassRenderer::Fragment),
+                    EffectPassKind::Compute => ComputeRenderer::new(
+                        device,
+                        &config.shader,
+                        &named,
+                        self.width,
+                        self.height,
+                        self.device_sample_count,
+                    )
+                    .map(PassRenderer::Compute),
+                };
+
+                renderer.map_err(pass_error)?
+            };
+
+            built.push(EffectPass {
+                name: config.name.clone(),
+                renderer,
+                config,
+                history,
+                target,
+            });
+        }
+
+        self.passes = built;
+
+        Ok(())
+    }
+
+    /// Rebuilds every pass at a new size. History is reset.
+    /// The chain renders at the size of its source, not the window's, so this is only needed
+    /// when the source changes size, e.g. for a different camera; the app keeps the chain at
+    /// the video size when the window is resized.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        resources: &EffectResources,
+        width: u32,
+        height: u32,
+    ) -> Result<(), RendererError> {
+        self.width = width;
+        self.height = height;
+
+        let passes = self.passes.iter().map(|p| p.config.clone()).collect();
+
+        self.build_passes(device, passes, resources)
+    }
+
+    pub fn uses_shader(&self, name: &str) -> bool {
+        self.passes.iter().any(|p| p.name == name)
+    }
+
+    /// The variants of the named shader the chain uses.
+    pub fn shader_variants(&self, name: &str) -> Vec<ShaderVariant> {
+        let mut variants: Vec<ShaderVariant> = vec![];
+
+        for pass in self.passes.iter().filter(|p| p.name == name) {
+            let variant = ShaderVariant {
+                kind: pass.config.kind,
+                defines: pass.config.defines.clone(),
+            };
+            if !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+
+        variants
+    }
+
+    /// The names of the shaders used by the chain, without duplicates.
+    pub fn shader_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+
+        for pass in self.passes.iter() {
+            if !names.contains(&pass.name) {
+                names.push(pass.name.clone());
+            }
+        }
+
+        names
+    }
+
+    pub fn output_texture(&self) -> &wgpu::Texture {
+        output_of(&self.passes, self.passes.last().unwrap())
+    }
+
+    /// Swaps in a new version of the named shader, compiled as `variant`. Only the passes
+    /// using that variant of the shader are changed.
+    /// If its bindings are unchanged only the pipelines of the passes using it are rebuilt.
+    /// Otherwise every pass is rebuilt, which resets history and replaces the output texture,
+    /// and true is returned. On error the chain is left as it was.
+    pub fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        variant: &ShaderVariant,
+        shader: EffectShader,
+        resources: &EffectResources,
+    ) -> Result<bool, RendererError> {
+        let shader = Rc::new(shader);
+        let uses = |p: &EffectPass| {
+            p.name == name && p.config.kind == variant.kind && p.config.defines == variant.defines
+        };
+
+        let same_bindings = self
+            .passes
+            .iter()
+            .filter(|p| uses(p))
+            .all(|p| p.config.shader.bindings == shader.bindings);
+
+        if !same_bindings {
+            println!("bindings of {} changed, rebuilding effect chain", name);
+            let passes = self
+                .passes
+                .iter()
+                .map(|p| {
+                    if uses(p) {
+                        PassConfig {
+                            shader: shader.clone(),
+                            ..p.config.clone()
+                        }
+                    } else {
+                        p.config.clone()
+                    }
+                })
+                .collect();
+
+            self.build_passes(device, passes, resources)?;
+            return Ok(true);
+        }
+
+        for pass in self.passes.iter_mut().filter(|p| uses(p)) {
+            println!("reloading effect pass {}", name);
+            match &mut pass.renderer {
+                PassRenderer::Fragment(renderer) => {
+                    renderer.rebuild_pipeline(device, &self.vs_mod, &shader.module)
+                }
+                PassRenderer::Compute(renderer) => {
+                    renderer.rebuild_pipeline(device, &shader.module)
+                }
+            }
+            pass.config.shader = shader.clone();
+        }
+
+        Ok(false)
+    }
+
+    /// Replaces every pass of the named shader with new passes, which take the place of the
+    /// first of them, e.g. when an imported ISF effect is reloaded and its passes may have
+    /// changed. Every pass is rebuilt, which resets history and replaces the output texture.
+    /// On error the chain is left as it was.
+    pub fn replace_passes(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        descriptors: Vec<EffectPassDescriptor>,
+        resources: &EffectResources,
+    ) -> Result<(), RendererError> {
+        let position = self
+            .passes
+            .iter()
+            .position(|p| p.name == name)
+            .unwrap_or(self.passes.len());
+
+        let mut passes: Vec<PassConfig> = self
+            .passes
+            .iter()
+            .filter(|p| p.name != name)
+            .map(|p| p.config.clone())
+            .collect();
+        passes.splice(
+            position..position,
+            descriptors.into_iter().map(PassConfig::from),
+        );
+
+        if passes.is_empty() {
+            return Err(RendererError::EmptyEffectChain);
+        }
+
+        self.build_passes(device, passes, resources)
+    }
+
+    /// Encodes every pass in order.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        for pass in self.passes.iter() {
+            match (&pass.renderer, pass.target) {
+                (PassRenderer::Fragment(renderer), Some(target)) => {
+                    let texture_view = self.passes[target].renderer.output_texture().view().build();
+                    renderer
+                        .render_to(encoder, &texture_view, wgpu::LoadOp::Load)
+                        .expect("blended passes are single sampled");
+                }
+                (renderer, _) => renderer.render(encoder),
+            }
+
+            pass.update_history(encoder, output_of(&self.passes, pass));
+        }
+    }
+
+    /// Clears the history of every pass, e.g. to restart trails or a simulation.
+    pub fn clear_history(&self, encoder: &mut wgpu::CommandEncoder) {
+        for pass in self.passes.iter() {
+            pass.clear_history(encoder);
+        }
+    }
+
+    /// Draws the output of the final pass to the given texture view.
+    pub fn present(&self, texture_view: &wgpu::TextureViewHandle, encoder: &mut wgpu::CommandEncoder) {
+        let last = self.passes.last().unwrap();
+        let presented = match last.target {
+            Some(target) => &self.passes[target],
+            None => last,
+        };
+
+        presented
+            .renderer
+            .texture_reshaper()
+            .encode_render_pass(texture_view, encoder);
+    }
+}
+
+/// The texture a pass's result ends up in: its own output, or the one it blends into.
+fn output_of<'a>(passes: &'a [EffectPass], pass: &'a EffectPass) -> &'a wgpu::Texture {
+    match pass.target {
+        Some(target) => passes[target].renderer.output_texture(),
+        None => pass.renderer.output_texture(),
+    }
+}
+
+fn copy_texture(encoder: &mut wgpu::CommandEncoder, src: &wgpu::Texture, dst: &wgpu::Texture) {
+    encoder.copy_texture_to_texture(src.as_image_copy(), dst.as_image_copy(), src.extent());
+}
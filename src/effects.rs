@@ -0,0 +1,669 @@
+use nannou::prelude::*;
+
+use crate::std140::*;
+use crate::uniforms::*;
+
+/// The directory, relative to the shaders directory, the built-in effects live in.
+pub const EFFECTS_DIR: &str = "effects";
+
+/// Room for one parameter block at the largest offset alignment wgpu allows.
+const PARAMS_SLOT_SIZE: u64 = 256;
+
+/// The built-in effects in `shaders/effects`. Each one samples the previous pass (`tex`) and
+/// is tuned by its own uniform block, see `EffectParams`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    EdgeDetect,
+    Pixelate,
+    Kaleidoscope,
+    Posterize,
+    ChromaticAberration,
+    Halftone,
+    Thermal,
+    Mirror,
+    Toon,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 9] = [
+        Effect::EdgeDetect,
+        Effect::Pixelate,
+        Effect::Kaleidoscope,
+        Effect::Posterize,
+        Effect::ChromaticAberration,
+        Effect::Halftone,
+        Effect::Thermal,
+        Effect::Mirror,
+        Effect::Toon,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::EdgeDetect => "edge_detect",
+            Effect::Pixelate => "pixelate",
+            Effect::Kaleidoscope => "kaleidoscope",
+            Effect::Posterize => "posterize",
+            Effect::ChromaticAberration => "chromatic_aberration",
+            Effect::Halftone => "halftone",
+            Effect::Thermal => "thermal",
+            Effect::Mirror => "mirror",
+            Effect::Toon => "toon",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Effect> {
+        Effect::ALL.iter().copied().find(|e| e.name() == name)
+    }
+
+    /// The effect's shader, relative to the shaders directory, e.g. `effects/pixelate.frag`.
+    pub fn shader_filename(&self) -> String {
+        format!("{}/{}.frag", EFFECTS_DIR, self.name())
+    }
+
+    /// The name the effect's shader includes its generated parameter block by.
+    pub fn glsl_include_name(&self) -> String {
+        format!("{}_params.glsl", self.name())
+    }
+
+    /// The name of the effect's parameter block, which it is bound to the chain by.
+    pub fn block_name(&self) -> &'static str {
+        match self {
+            Effect::EdgeDetect => "EdgeDetectParams",
+            Effect::Pixelate => "PixelateParams",
+            Effect::Kaleidoscope => "KaleidoscopeParams",
+            Effect::Posterize => "PosterizeParams",
+            Effect::ChromaticAberration => "ChromaticAberrationParams",
+            Effect::Halftone => "HalftoneParams",
+            Effect::Thermal => "ThermalParams",
+            Effect::Mirror => "MirrorParams",
+            Effect::Toon => "ToonParams",
+        }
+    }
+
+    pub fn default_params(&self) -> EffectParams {
+        match self {
+            Effect::EdgeDetect => EffectParams::EdgeDetect(Default::default()),
+            Effect::Pixelate => EffectParams::Pixelate(Default::default()),
+            Effect::Kaleidoscope => EffectParams::Kaleidoscope(Default::default()),
+            Effect::Posterize => EffectParams::Posterize(Default::default()),
+            Effect::ChromaticAberration => EffectParams::ChromaticAberration(Default::default()),
+            Effect::Halftone => EffectParams::Halftone(Default::default()),
+            Effect::Thermal => EffectParams::Thermal(Default::default()),
+            Effect::Mirror => EffectParams::Mirror(Default::default()),
+            Effect::Toon => EffectParams::Toon(Default::default()),
+        }
+    }
+}
+
+/// Sobel edges, drawn in `color` over black or over the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeDetectParams {
+    /// Scales the gradient before thresholding.
+    pub strength: f32,
+    /// Gradients below this are not edges.
+    pub threshold: f32,
+    /// 0 draws edges over black, 1 over the image.
+    pub overlay: f32,
+    pub color: [f32; 4],
+}
+
+impl Default for EdgeDetectParams {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            threshold: 0.1,
+            overlay: 0.0,
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl UniformBlock for EdgeDetectParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::EdgeDetect.block_name())
+            .field("edge_strength", UniformType::Float)
+            .field("edge_threshold", UniformType::Float)
+            .field("edge_overlay", UniformType::Float)
+            .field("edge_color", UniformType::Vec4)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer
+            .write(self.strength)
+            .write(self.threshold)
+            .write(self.overlay)
+            .write(self.color);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelateParams {
+    /// The size of a block, in output pixels.
+    pub pixel_size: f32,
+}
+
+impl Default for PixelateParams {
+    fn default() -> Self {
+        Self { pixel_size: 12.0 }
+    }
+}
+
+impl UniformBlock for PixelateParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::Pixelate.block_name())
+            .field("pixel_size", UniformType::Float)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer.write(self.pixel_size);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KaleidoscopeParams {
+    pub segments: f32,
+    /// In radians.
+    pub rotation: f32,
+    /// In texture coordinates.
+    pub center: [f32; 2],
+    /// Centers the kaleidoscope on the face while one is detected.
+    pub follow_face: bool,
+}
+
+impl Default for KaleidoscopeParams {
+    fn default() -> Self {
+        Self {
+            segments: 6.0,
+            rotation: 0.0,
+            center: [0.5, 0.5],
+            follow_face: false,
+        }
+    }
+}
+
+impl UniformBlock for KaleidoscopeParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::Kaleidoscope.block_name())
+            .field("kaleidoscope_segments", UniformType::Float)
+            .field("kaleidoscope_rotation", UniformType::Float)
+            .field("kaleidoscope_center", UniformType::Vec2)
+            .field("kaleidoscope_follow_face", UniformType::Uint)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer
+            .write(self.segments)
+            .write(self.rotation)
+            .write(self.center)
+            .write(self.follow_face);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PosterizeParams {
+    /// Color levels per channel.
+    pub levels: f32,
+    /// Applied before quantizing, values below 1 keep more levels in the shadows.
+    pub gamma: f32,
+}
+
+impl Default for PosterizeParams {
+    fn default() -> Self {
+        Self {
+            levels: 5.0,
+            gamma: 0.6,
+        }
+    }
+}
+
+impl UniformBlock for PosterizeParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::Posterize.block_name())
+            .field("posterize_levels", UniformType::Float)
+            .field("posterize_gamma", UniformType::Float)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer.write(self.levels).write(self.gamma);
+    }
+}
+
+/// Splits the red and blue channels away from `center`, more towards the edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChromaticAberrationParams {
+    /// The offset at the corners, in texture coordinates.
+    pub amount: f32,
+    pub center: [f32; 2],
+}
+
+impl Default for ChromaticAberrationParams {
+    fn default() -> Self {
+        Self {
+            amount: 0.006,
+            center: [0.5, 0.5],
+        }
+    }
+}
+
+impl UniformBlock for ChromaticAberrationParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::ChromaticAberration.block_name())
+            .field("aberration_amount", UniformType::Float)
+            .field("aberration_center", UniformType::Vec2)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer.write(self.amount).write(self.center);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalftoneParams {
+    /// The spacing of the dot grid, in output pixels.
+    pub dot_size: f32,
+    /// The rotation of the dot grid, in radians.
+    pub angle: f32,
+    pub ink: [f32; 4],
+    pub paper: [f32; 4],
+}
+
+impl Default for HalftoneParams {
+    fn default() -> Self {
+        Self {
+            dot_size: 8.0,
+            angle: std::f32::consts::FRAC_PI_4,
+            ink: [0.05, 0.05, 0.1, 1.0],
+            paper: [0.96, 0.94, 0.88, 1.0],
+        }
+    }
+}
+
+impl UniformBlock for HalftoneParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::Halftone.block_name())
+            .field("halftone_dot_size", UniformType::Float)
+            .field("halftone_angle", UniformType::Float)
+            .field("halftone_ink", UniformType::Vec4)
+            .field("halftone_paper", UniformType::Vec4)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer
+            .write(self.dot_size)
+            .write(self.angle)
+            .write(self.ink)
+            .write(self.paper);
+    }
+}
+
+/// Maps brightness onto a thermal camera palette.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalParams {
+    pub contrast: f32,
+    /// Added to the brightness before the palette lookup.
+    pub offset: f32,
+}
+
+impl Default for ThermalParams {
+    fn default() -> Self {
+        Self {
+            contrast: 1.2,
+            offset: 0.0,
+        }
+    }
+}
+
+impl UniformBlock for ThermalParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::Thermal.block_name())
+            .field("thermal_contrast", UniformType::Float)
+            .field("thermal_offset", UniformType::Float)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer.write(self.contrast).write(self.offset);
+    }
+}
+
+/// Which half of the image is reflected onto the other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MirrorMode {
+    LeftToRight,
+    RightToLeft,
+    TopToBottom,
+    BottomToTop,
+    /// The top left quarter reflected into every quarter.
+    Quad,
+}
+
+impl MirrorMode {
+    const ALL: [MirrorMode; 5] = [
+        MirrorMode::LeftToRight,
+        MirrorMode::RightToLeft,
+        MirrorMode::TopToBottom,
+        MirrorMode::BottomToTop,
+        MirrorMode::Quad,
+    ];
+
+    fn glsl_name(&self) -> &'static str {
+        match self {
+            MirrorMode::LeftToRight => "MIRROR_LEFT_TO_RIGHT",
+            MirrorMode::RightToLeft => "MIRROR_RIGHT_TO_LEFT",
+            MirrorMode::TopToBottom => "MIRROR_TOP_TO_BOTTOM",
+            MirrorMode::BottomToTop => "MIRROR_BOTTOM_TO_TOP",
+            MirrorMode::Quad => "MIRROR_QUAD",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MirrorParams {
+    pub mode: MirrorMode,
+    /// Where the reflection happens, in texture coordinates along the mirrored axis.
+    pub axis: f32,
+}
+
+impl Default for MirrorParams {
+    fn default() -> Self {
+        Self {
+            mode: MirrorMode::LeftToRight,
+            axis: 0.5,
+        }
+    }
+}
+
+impl UniformBlock for MirrorParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::Mirror.block_name())
+            .field("mirror_mode", UniformType::Uint)
+            .field("mirror_axis", UniformType::Float)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer.write(self.mode as u32).write(self.axis);
+    }
+}
+
+/// Flat shading with ink outlines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToonParams {
+    /// Brightness levels.
+    pub levels: f32,
+    /// Gradients above this are outlined.
+    pub edge_threshold: f32,
+    /// The outline width, in output pixels.
+    pub edge_width: f32,
+    pub saturation: f32,
+}
+
+impl Default for ToonParams {
+    fn default() -> Self {
+        Self {
+            levels: 4.0,
+            edge_threshold: 0.3,
+            edge_width: 1.5,
+            saturation: 1.3,
+        }
+    }
+}
+
+impl UniformBlock for ToonParams {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder(Effect::Toon.block_name())
+            .field("toon_levels", UniformType::Float)
+            .field("toon_edge_threshold", UniformType::Float)
+            .field("toon_edge_width", UniformType::Float)
+            .field("toon_saturation", UniformType::Float)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer
+            .write(self.levels)
+            .write(self.edge_threshold)
+            .write(self.edge_width)
+            .write(self.saturation);
+    }
+}
+
+/// The parameters of one of the built-in effects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EffectParams {
+    EdgeDetect(EdgeDetectParams),
+    Pixelate(PixelateParams),
+    Kaleidoscope(KaleidoscopeParams),
+    Posterize(PosterizeParams),
+    ChromaticAberration(ChromaticAberrationParams),
+    Halftone(HalftoneParams),
+    Thermal(ThermalParams),
+    Mirror(MirrorParams),
+    Toon(ToonParams),
+}
+
+impl EffectParams {
+    pub fn effect(&self) -> Effect {
+        match self {
+            EffectParams::EdgeDetect(_) => Effect::EdgeDetect,
+            EffectParams::Pixelate(_) => Effect::Pixelate,
+            EffectParams::Kaleidoscope(_) => Effect::Kaleidoscope,
+            EffectParams::Posterize(_) => Effect::Posterize,
+            EffectParams::ChromaticAberration(_) => Effect::ChromaticAberration,
+            EffectParams::Halftone(_) => Effect::Halftone,
+            EffectParams::Thermal(_) => Effect::Thermal,
+            EffectParams::Mirror(_) => Effect::Mirror,
+            EffectParams::Toon(_) => Effect::Toon,
+        }
+    }
+
+    pub fn layout(&self) -> UniformLayout {
+        match self {
+            EffectParams::EdgeDetect(_) => EdgeDetectParams::layout(),
+            EffectParams::Pixelate(_) => PixelateParams::layout(),
+            EffectParams::Kaleidoscope(_) => KaleidoscopeParams::layout(),
+            EffectParams::Posterize(_) => PosterizeParams::layout(),
+            EffectParams::ChromaticAberration(_) => ChromaticAberrationParams::layout(),
+            EffectParams::Halftone(_) => HalftoneParams::layout(),
+            EffectParams::Thermal(_) => ThermalParams::layout(),
+            EffectParams::Mirror(_) => MirrorParams::layout(),
+            EffectParams::Toon(_) => ToonParams::layout(),
+        }
+    }
+
+    fn set(&self, arena: &mut UniformArena, slot: UniformSlot) {
+        match self {
+            EffectParams::EdgeDetect(params) => arena.set(slot, params),
+            EffectParams::Pixelate(params) => arena.set(slot, params),
+            EffectParams::Kaleidoscope(params) => arena.set(slot, params),
+            EffectParams::Posterize(params) => arena.set(slot, params),
+            EffectParams::ChromaticAberration(params) => arena.set(slot, params),
+            EffectParams::Halftone(params) => arena.set(slot, params),
+            EffectParams::Thermal(params) => arena.set(slot, params),
+            EffectParams::Mirror(params) => arena.set(slot, params),
+            EffectParams::Toon(params) => arena.set(slot, params),
+        }
+    }
+}
+
+/// The parameter blocks of the built-in effects in a chain, packed into one uniform arena.
+///
+/// Add the parameters of every built-in effect the chain uses and pass `bindings` as the
+/// chain's `uniform_buffers`. Each effect binds its block by name, so a chain can only use
+/// each built-in effect once.
+pub struct EffectLibrary {
+    arena: UniformArena,
+    params: Vec<(EffectParams, UniformSlot)>,
+}
+
+impl EffectLibrary {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            arena: UniformArena::new(device, Effect::ALL.len() as u64 * PARAMS_SLOT_SIZE),
+            params: vec![],
+        }
+    }
+
+    /// A library with the default parameters of every built-in effect, so any of them can be
+    /// used in the chain.
+    pub fn with_defaults(device: &wgpu::Device) -> Self {
+        let mut library = Self::new(device);
+        for effect in Effect::ALL.iter() {
+            library.add(effect.default_params());
+        }
+        library
+    }
+
+    /// Adds an effect's parameters, or replaces them if it was already added.
+    pub fn add(&mut self, params: EffectParams) {
+        if let Some(existing) = self.get_mut(params.effect()) {
+            *existing = params;
+            return;
+        }
+
+        let slot = self.arena.allocate(&params.layout());
+        self.params.push((params, slot));
+    }
+
+    pub fn get(&self, effect: Effect) -> Option<&EffectParams> {
+        self.params
+            .iter()
+            .find(|(p, _)| p.effect() == effect)
+            .map(|(p, _)| p)
+    }
+
+    /// The parameters of an effect, to tune it. Changes take effect on the next `update`.
+    pub fn get_mut(&mut self, effect: Effect) -> Option<&mut EffectParams> {
+        self.params
+            .iter_mut()
+            .find(|(p, _)| p.effect() == effect)
+            .map(|(p, _)| p)
+    }
+
+    /// The parameter blocks by name, for `EffectResources::uniform_buffers`.
+    pub fn bindings(&self) -> Vec<(&'static str, wgpu::BufferBinding)> {
+        self.params
+            .iter()
+            .map(|(p, slot)| (p.effect().block_name(), self.arena.binding(*slot)))
+            .collect()
+    }
+
+    /// Uploads the parameters that changed.
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        for (params, slot) in self.params.iter() {
+            params.set(&mut self.arena, *slot);
+        }
+
+        self.arena.update(queue);
+    }
+}
+
+/// The contents of an effect's `<name>_params.glsl` include, generated from its parameters.
+/// Define EFFECT_PARAMS_BINDING before including it, or effect.glsl, if the block is not at
+/// binding 10.
+pub fn glsl_include(requested: &str) -> Option<String> {
+    let effect = Effect::ALL
+        .iter()
+        .find(|e| e.glsl_include_name() == requested)?;
+
+    let guard = format!("{}_PARAMS_GLSL", effect.name().to_uppercase());
+    let mut glsl = format!(
+        "// Generated from effects::{}, do not edit.
+
+#ifndef {}
+#define {}
+
+#ifndef EFFECT_PARAMS_BINDING
+#define EFFECT_PARAMS_BINDING 10
+#endif
+
+",
+        effect.block_name(),
+        guard,
+        guard
+    );
+
+    if let Effect::Mirror = effect {
+        for mode in MirrorMode::ALL.iter() {
+            glsl.push_str(&format!("#define {} {}u\n", mode.glsl_name(), *mode as u32));
+        }
+        glsl.push('\n');
+    }
+
+    glsl.push_str(
+        &effect
+            .default_params()
+            .layout()
+            .glsl_block(0, "EFFECT_PARAMS_BINDING"),
+    );
+    glsl.push_str("\n#endif\n");
+    Some(glsl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect;
+    use crate::render;
+
+    /// Compiles a shader that includes an effect's generated parameter block, as the effect's
+    /// own shader does, and reflects the block's members.
+    fn compiled_params_block(effect: Effect) -> reflect::ShaderBinding {
+        let source = format!(
+            "#version 450
+#include \"{}\"
+layout(location = 0) out vec4 frag_color;
+void main() {{
+    frag_color = vec4(1.0);
+}}
+",
+            effect.glsl_include_name()
+        );
+
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        let mut options = shaderc::CompileOptions::new().unwrap();
+        options.set_include_callback(|requested, _, _, _| {
+            glsl_include(requested)
+                .map(|content| shaderc::ResolvedInclude {
+                    resolved_name: requested.to_string(),
+                    content,
+                })
+                .ok_or_else(|| format!("no generated include named {}", requested))
+        });
+
+        let spirv = compiler
+            .compile_into_spirv(
+                &source,
+                shaderc::ShaderKind::Fragment,
+                &effect.shader_filename(),
+                "main",
+                Some(&options),
+            )
+            .unwrap();
+
+        reflect::reflect_spirv(spirv.as_binary_u8())
+            .unwrap()
+            .into_iter()
+            .find(|b| b.name == effect.block_name())
+            .unwrap()
+    }
+
+    #[test]
+    fn params_layouts_match_their_glsl_includes() {
+        for effect in Effect::ALL.iter() {
+            let layout = effect.default_params().layout();
+            assert_eq!(layout.name, effect.block_name());
+
+            let block = compiled_params_block(*effect);
+            assert_eq!(block.members.len(), layout.fields.len(), "{}", layout.name);
+            render::check_uniform_layout(&block, &layout)
+                .unwrap_or_else(|e| panic!("{}: {}", layout.name, e));
+        }
+    }
+}
@@ -1,83 +1,50 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread;
-
 use mediapipe;
 use nannou::prelude::*;
 use opencv::prelude::*;
 
+use crate::detector::*;
 use crate::util;
+use crate::video_capture::FrameTag;
+
+impl Detector for mediapipe::face_mesh::FaceMeshDetector {
+    type Output = Vec<mediapipe::FaceMesh>;
+
+    fn process(&mut self, frame: &Mat) -> Self::Output {
+        mediapipe::face_mesh::FaceMeshDetector::process(self, frame)
+    }
+}
 
 pub struct FullFaceDetector {
     faces: Vec<mediapipe::FaceMesh>,
-    request_sender: Sender<Mat>,
-    response_receiver: Receiver<Vec<mediapipe::FaceMesh>>,
-    worker_thread: thread::JoinHandle<()>,
+    worker: DetectorWorker<mediapipe::face_mesh::FaceMeshDetector>,
 }
 
-impl FullFaceDetector {
-    pub fn new(video_size: Vec2) -> Self {
-        let (request_sender, request_receiver) = channel::<Mat>();
-        let (response_sender, response_receiver) = channel::<Vec<mediapipe::FaceMesh>>();
-
-        let worker_thread = thread::spawn(move || {
-            let mut detector = mediapipe::face_mesh::FaceMeshDetector::default();
-
-            let mut rgb_frame = unsafe {
-                opencv::core::Mat::new_rows_cols(
-                    video_size.x.round() as i32,
-                    video_size.y.round() as i32,
-                    opencv::core::CV_8UC3,
-                )
-                .unwrap()
-            };
-
-            let mut flip_frame = unsafe {
-                opencv::core::Mat::new_rows_cols(
-                    video_size.x.round() as i32,
-                    video_size.y.round() as i32,
-                    opencv::core::CV_8UC3,
-                )
-                .unwrap()
-            };
-
-            for frame in request_receiver.iter() {
-                opencv::imgproc::cvt_color(
-                    &frame,
-                    &mut rgb_frame,
-                    opencv::imgproc::COLOR_BGR2RGB,
-                    0,
-                )
-                .unwrap();
-
-                opencv::core::flip(&rgb_frame, &mut flip_frame, 1).unwrap(); // horizontal
-
-                println!("processing");
-                let result = detector.process(&flip_frame);
-                println!("found {} faces", result.len());
-
-                response_sender.send(result).unwrap();
-            }
-        });
+impl Default for FullFaceDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl FullFaceDetector {
+    pub fn new() -> Self {
         Self {
             faces: vec![],
-            request_sender,
-            response_receiver,
-            worker_thread,
+            worker: DetectorWorker::new("face mesh", Default::default),
         }
     }
 
-    pub fn update(&mut self, frame: &Mat) {
-        self.request_sender.send(frame.clone()).unwrap();
+    pub fn update(&mut self, tag: FrameTag, frame: &Mat) {
+        self.worker.send(tag, frame);
 
-        match self.response_receiver.try_recv() {
-            Ok(result) => {
-                if !result.is_empty() {
-                    self.faces = result;
-                }
+        if let Some(result) = self.worker.try_recv() {
+            if !result.output.is_empty() {
+                self.faces = result.output;
             }
-            Err(_) => return,
-        };
+        }
+    }
+
+    pub fn stats(&self) -> DetectorStats {
+        self.worker.stats()
     }
 
     pub fn draw_faces(&self, draw: &Draw, _video_size: &Vec2, draw_size: &Vec2) {
@@ -0,0 +1,55 @@
+use std::collections::VecDeque;
+
+use opencv::prelude::*;
+
+use crate::video_capture::FrameTag;
+
+/// How many frames to keep by default, enough to cover a detector running at a few frames per
+/// second behind a 30 fps camera.
+pub const DEFAULT_CAPACITY: usize = 8;
+
+/// The most recent camera frames, so the video can be delayed to match the frame a detection
+/// was computed from.
+///
+/// Frames that fall out of the history have their buffers reused for new ones.
+pub struct FrameHistory {
+    frames: VecDeque<(FrameTag, Mat)>,
+    capacity: usize,
+}
+
+impl FrameHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn push(&mut self, tag: FrameTag, frame: &Mat) {
+        let mut buffer = if self.frames.len() >= self.capacity {
+            self.frames.pop_front().map(|(_, buffer)| buffer).unwrap()
+        } else {
+            Mat::default()
+        };
+
+        match frame.copy_to(&mut buffer) {
+            Ok(()) => self.frames.push_back((tag, buffer)),
+            Err(e) => println!("unable to keep frame {}: {}", tag.sequence, e),
+        }
+    }
+
+    /// The frame with the given sequence number. If it has already been dropped the oldest
+    /// frame is returned instead, and if it was never kept the newest older frame is.
+    pub fn get(&self, sequence: u64) -> Option<&Mat> {
+        self.frames
+            .iter()
+            .rev()
+            .find(|(tag, _)| tag.sequence <= sequence)
+            .or_else(|| self.frames.front())
+            .map(|(_, frame)| frame)
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
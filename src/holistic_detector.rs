@@ -1,82 +1,118 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread;
-
 use mediapipe;
 use nannou::prelude::*;
 use opencv::prelude::*;
 
+use std::time::{Duration, Instant};
+
+use crate::detector::*;
+use crate::prediction::LandmarkPredictor;
+use crate::smoothing::{LandmarkSmoother, Smoothing};
 use crate::util;
-use crate::faces;
+use crate::video_capture::FrameTag;
+
+impl Detector for mediapipe::holistic::HolisticDetector {
+    type Output = mediapipe::holistic::HolisticDetection;
+
+    fn process(&mut self, frame: &Mat) -> Self::Output {
+        mediapipe::holistic::HolisticDetector::process(self, frame)
+    }
+}
 
 pub struct HolisticDetector {
     detections: Vec<mediapipe::holistic::HolisticDetection>,
-    request_sender: Sender<Mat>,
-    response_receiver: Receiver<Vec<mediapipe::holistic::HolisticDetection>>,
-    _worker_thread: thread::JoinHandle<()>,
+    detection_tag: Option<FrameTag>,
+    smoother: LandmarkSmoother,
+    predictor: LandmarkPredictor,
+    worker: DetectorWorker<mediapipe::holistic::HolisticDetector>,
 }
 
-impl HolisticDetector {
-    pub fn new(video_size: Vec2) -> Self {
-        let (request_sender, request_receiver) = channel::<Mat>();
-        let (response_sender, response_receiver) = channel::<Vec<mediapipe::holistic::HolisticDetection>>();
-
-        let worker_thread = thread::spawn(move || {
-            let mut detector = mediapipe::holistic::HolisticDetector::default();
-
-            let mut rgb_frame = unsafe {
-                opencv::core::Mat::new_rows_cols(
-                    video_size.x.round() as i32,
-                    video_size.y.round() as i32,
-                    opencv::core::CV_8UC3,
-                )
-                .unwrap()
-            };
-
-            let mut flip_frame = unsafe {
-                opencv::core::Mat::new_rows_cols(
-                    video_size.x.round() as i32,
-                    video_size.y.round() as i32,
-                    opencv::core::CV_8UC3,
-                )
-                .unwrap()
-            };
-
-            for frame in request_receiver.iter() {
-                opencv::imgproc::cvt_color(
-                    &frame,
-                    &mut rgb_frame,
-                    opencv::imgproc::COLOR_BGR2RGB,
-                    0,
-                )
-                .unwrap();
-
-                opencv::core::flip(&rgb_frame, &mut flip_frame, 1).unwrap(); // horizontal
-
-                let result = detector.process(&flip_frame);
-
-                response_sender.send(vec![result]).unwrap();
-            }
-        });
+impl Default for HolisticDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl HolisticDetector {
+    pub fn new() -> Self {
         Self {
             detections: vec![],
-            request_sender,
-            response_receiver,
-            _worker_thread: worker_thread,
+            detection_tag: None,
+            smoother: LandmarkSmoother::default(),
+            predictor: LandmarkPredictor::default(),
+            worker: DetectorWorker::new("holistic", Default::default),
         }
     }
 
-    pub fn update(&mut self, frame: &Mat) {
-        self.request_sender.send(frame.clone()).unwrap();
+    pub fn update(&mut self, tag: FrameTag, frame: &Mat) {
+        self.worker.send(tag, frame);
 
-        match self.response_receiver.try_recv() {
-            Ok(result) => {
-                if !result.is_empty() {
-                    self.detections = result;
-                }
-            }
-            Err(_) => return,
-        };
+        if let Some(mut result) = self.worker.try_recv() {
+            self.smoother.apply(&mut result.output, result.tag.captured);
+            self.predictor.observe(&result.output, result.tag.captured);
+            self.detections = vec![result.output];
+            self.detection_tag = Some(result.tag);
+        }
+    }
+
+    pub fn smoothing(&self) -> Smoothing {
+        self.smoother.smoothing()
+    }
+
+    /// Sets how landmarks are smoothed, starting the filters over.
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        self.smoother.set_smoothing(smoothing);
+    }
+
+    /// Moves the latest detection's landmarks to where they're predicted to be at `time`,
+    /// so they keep moving between detections. Called once per rendered frame.
+    pub fn predict(&mut self, time: Instant) {
+        if let Some(detection) = self.detections.first_mut() {
+            self.predictor.predict(detection, time);
+        }
+    }
+
+    pub fn max_prediction(&self) -> Duration {
+        self.predictor.max_horizon()
+    }
+
+    /// Caps how far past a detection landmarks are predicted. Zero turns prediction off.
+    pub fn set_max_prediction(&mut self, max_horizon: Duration) {
+        self.predictor.set_max_horizon(max_horizon);
+    }
+
+    pub fn stats(&self) -> DetectorStats {
+        self.worker.stats()
+    }
+
+    pub fn latest(&self) -> Option<&mediapipe::holistic::HolisticDetection> {
+        self.detections.first()
+    }
+
+    /// The frame the latest detection was computed from.
+    pub fn latest_tag(&self) -> Option<FrameTag> {
+        self.detection_tag
+    }
+
+    pub fn pose_present(&self) -> bool {
+        self.latest().map_or(false, |d| d.pose.is_some())
+    }
+
+    /// The centre of the face mesh, in normalized coordinates.
+    pub fn face_center(&self) -> Option<Vec2> {
+        let face = self.latest()?.face.as_ref()?;
+        Some(util::landmarks_center(&face.data))
+    }
+
+    /// The centre of the left hand, in normalized coordinates.
+    pub fn left_hand_center(&self) -> Option<Vec2> {
+        let hand = self.latest()?.left_hand.as_ref()?;
+        Some(util::landmarks_center(&hand.data))
+    }
+
+    /// The centre of the right hand, in normalized coordinates.
+    pub fn right_hand_center(&self) -> Option<Vec2> {
+        let hand = self.latest()?.right_hand.as_ref()?;
+        Some(util::landmarks_center(&hand.data))
     }
 
     fn draw_detection(&self, draw: &Draw, detection: &mediapipe::holistic::HolisticDetection, mapper: &impl Fn(&Vec2) -> Vec2) {
@@ -0,0 +1,907 @@
+use std::fmt;
+use std::ops::Range;
+
+use nannou::prelude::*;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::std140::*;
+
+/// The file extension of ISF and Shadertoy effects.
+pub const EXTENSION: &str = "fs";
+
+/// The block name the inputs of an imported effect are bound by.
+pub const BLOCK_NAME: &str = "IsfInputs";
+
+#[derive(Debug)]
+pub enum IsfError {
+    Json(String),
+    /// Files without an ISF header are read as Shadertoy shaders, which need a `mainImage`.
+    MissingMainImage,
+    UnsupportedInput {
+        name: String,
+        ty: String,
+    },
+    InvalidInput {
+        name: String,
+        message: String,
+    },
+    UnknownInput(String),
+    /// A pass target is read where the linear effect chain can't provide it. `pass` is the
+    /// reading pass, if the read is inside an `if (PASSINDEX == n)` block.
+    UnsupportedTargetRead {
+        target: String,
+        pass: Option<usize>,
+    },
+}
+
+impl fmt::Display for IsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IsfError::Json(message) => write!(f, "invalid ISF header: {}", message),
+            IsfError::MissingMainImage => write!(
+                f,
+                "no ISF header and no mainImage function, so this is neither an ISF nor a Shadertoy shader"
+            ),
+            IsfError::UnsupportedInput { name, ty } => {
+                write!(f, "input {} has unsupported type {}", name, ty)
+            }
+            IsfError::InvalidInput { name, message } => {
+                write!(f, "input {} is invalid: {}", name, message)
+            }
+            IsfError::UnknownInput(name) => write!(f, "the effect has no input named {}", name),
+            IsfError::UnsupportedTargetRead { target, pass } => {
+                match pass {
+                    Some(pass) => write!(f, "pass {} reads {}", pass, target)?,
+                    None => write!(f, "{} is read but no pass can read it", target)?,
+                }
+                write!(
+                    f,
+                    ", a target can only be read by the pass after the one rendering it, and by that pass if it's persistent"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for IsfError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IsfFormat {
+    Isf,
+    Shadertoy,
+}
+
+/// The value of a non-image input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IsfValue {
+    Float(f32),
+    Long(i32),
+    Bool(bool),
+    Color([f32; 4]),
+    Point2D([f32; 2]),
+}
+
+impl IsfValue {
+    fn uniform_type(&self) -> UniformType {
+        match self {
+            IsfValue::Float(_) => UniformType::Float,
+            IsfValue::Long(_) => UniformType::Int,
+            IsfValue::Bool(_) => UniformType::Uint,
+            IsfValue::Color(_) => UniformType::Vec4,
+            IsfValue::Point2D(_) => UniformType::Vec2,
+        }
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        match *self {
+            IsfValue::Float(value) => writer.write(value),
+            IsfValue::Long(value) => writer.write(value),
+            IsfValue::Bool(value) => writer.write(value),
+            IsfValue::Color(value) => writer.write(value),
+            IsfValue::Point2D(value) => writer.write(value),
+        };
+    }
+}
+
+/// An entry of the ISF `INPUTS` list. Image inputs have no value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsfInput {
+    pub name: String,
+    pub label: Option<String>,
+    pub default: Option<IsfValue>,
+    pub min: Option<IsfValue>,
+    pub max: Option<IsfValue>,
+}
+
+impl IsfInput {
+    pub fn is_image(&self) -> bool {
+        self.default.is_none()
+    }
+
+    /// The name of the input's field in the uniform block. GLSL can't put bools in uniform
+    /// blocks, so bool inputs are stored as uints and converted by a macro of the input's name.
+    fn field_name(&self) -> String {
+        match self.default {
+            Some(IsfValue::Bool(_)) => format!("isf_bool_{}", self.name),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+/// An entry of the ISF `PASSES` list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IsfPass {
+    pub target: Option<String>,
+    pub persistent: bool,
+}
+
+/// An ISF (Interactive Shader Format) or Shadertoy shader, wrapped to run as effect chain passes.
+///
+/// Each pass becomes a fragment pass that includes `effect.glsl`, so the effect can use
+/// everything other effects can. The conventions of the original format are mapped onto it:
+/// - the first ISF image input and Shadertoy's `iChannel0` are the camera (`video`), and
+///   `iChannel1` is the previous pass's output (`tex`)
+/// - other ISF image inputs are named inputs of the chain, bound by their ISF name
+/// - `TIME`, `TIMEDELTA`, `FRAMEINDEX`, `RENDERSIZE`, `iTime`, `iTimeDelta`, `iFrame`,
+///   `iResolution` and `iMouse` come from `Uniforms`, so Shadertoy shaders need nothing else;
+///   `RENDERSIZE` and `iResolution` are the chain's `render_size`, not the window's
+/// - the values of the other ISF inputs are in the effect's `IsfInputs` block, which is only
+///   declared if it has any, see `IsfParameters`
+///
+/// The chain is linear, so a pass's target is only readable by the pass after it, as `tex`,
+/// and by the pass itself on the next frame if it is persistent, as `previous_frame`. Effects
+/// that read a target anywhere else are rejected; reads inside `if (PASSINDEX == n)` blocks are
+/// attributed to pass n, other reads only need some pass to be able to make them.
+/// Pass sizes are ignored and every pass renders at the chain's size.
+/// `gl_FragCoord` has its origin at the top left, use `isf_FragNormCoord` in ISF shaders.
+#[derive(Debug, Clone)]
+pub struct IsfEffect {
+    pub format: IsfFormat,
+    pub description: Option<String>,
+    pub inputs: Vec<IsfInput>,
+    pub passes: Vec<IsfPass>,
+    /// The shader without its header, padded so line numbers match the original file.
+    body: String,
+}
+
+impl IsfEffect {
+    pub fn parse(source: &str) -> Result<Self, IsfError> {
+        match split_header(source) {
+            Some((header, body)) => Self::parse_isf(header, body),
+            None => {
+                let main_image = Regex::new(r"\bmainImage\s*\(").unwrap();
+                if !main_image.is_match(source) {
+                    return Err(IsfError::MissingMainImage);
+                }
+
+                Ok(Self {
+                    format: IsfFormat::Shadertoy,
+                    description: None,
+                    inputs: vec![],
+                    passes: vec![IsfPass {
+                        target: None,
+                        persistent: false,
+                    }],
+                    body: source.to_string(),
+                })
+            }
+        }
+    }
+
+    fn parse_isf(header: &str, body: String) -> Result<Self, IsfError> {
+        let json: Value =
+            serde_json::from_str(header).map_err(|e| IsfError::Json(e.to_string()))?;
+
+        let inputs = match json.get("INPUTS") {
+            Some(Value::Array(inputs)) => inputs
+                .iter()
+                .filter_map(|input| parse_input(input).transpose())
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![],
+        };
+
+        for (i, input) in inputs.iter().enumerate() {
+            if inputs[..i].iter().any(|other| other.name == input.name) {
+                return Err(IsfError::InvalidInput {
+                    name: input.name.clone(),
+                    message: "declared more than once".to_string(),
+                });
+            }
+        }
+
+        // ISF 1 lists persistent buffers separately, ISF 2 marks the pass
+        let persistent_buffers: Vec<String> = match json.get("PERSISTENT_BUFFERS") {
+            Some(Value::Array(names)) => names
+                .iter()
+                .filter_map(|n| n.as_str().map(str::to_string))
+                .collect(),
+            Some(Value::Object(buffers)) => buffers.keys().cloned().collect(),
+            _ => vec![],
+        };
+
+        let mut passes: Vec<IsfPass> = match json.get("PASSES") {
+            Some(Value::Array(passes)) => passes
+                .iter()
+                .map(|pass| {
+                    let target = pass.get("TARGET").and_then(Value::as_str);
+                    IsfPass {
+                        target: target.map(str::to_string),
+                        persistent: pass.get("PERSISTENT").map_or(false, is_truthy)
+                            || target.map_or(false, |t| persistent_buffers.iter().any(|b| b == t)),
+                    }
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        if passes.is_empty() {
+            passes.push(IsfPass {
+                target: None,
+                persistent: false,
+            });
+        }
+
+        check_target_reads(&passes, &body)?;
+
+        Ok(Self {
+            format: IsfFormat::Isf,
+            description: json
+                .get("DESCRIPTION")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            inputs,
+            passes,
+            body,
+        })
+    }
+
+    /// How many previous frames pass `index` needs, 1 if its target is persistent.
+    pub fn history_frames(&self, index: usize) -> usize {
+        self.passes[index].persistent as usize
+    }
+
+    /// Whether the effect has inputs other than images, and so an `IsfInputs` block.
+    pub fn has_parameters(&self) -> bool {
+        self.inputs.iter().any(|i| !i.is_image())
+    }
+
+    /// The layout of the `IsfInputs` block: every non-image input.
+    pub fn layout(&self) -> UniformLayout {
+        let mut builder = UniformLayout::builder(BLOCK_NAME);
+        for input in self.inputs.iter() {
+            if let Some(default) = input.default {
+                builder = builder.field(&input.field_name(), default.uniform_type());
+            }
+        }
+        builder.build()
+    }
+
+    /// The GLSL source of pass `index`, to compile as a fragment shader.
+    pub fn pass_glsl(&self, index: usize) -> String {
+        let mut glsl = format!(
+            "#version 450
+// Generated from an {} shader by isf::IsfEffect, pass {}.
+
+#define EFFECT_HISTORY_FRAMES {}
+#include \"effect.glsl\"
+
+",
+            match self.format {
+                IsfFormat::Isf => "ISF",
+                IsfFormat::Shadertoy => "Shadertoy",
+            },
+            index,
+            self.history_frames(index)
+        );
+
+        if self.has_parameters() {
+            glsl.push_str(&self.layout().glsl_block(0, "EFFECT_USER_BINDING"));
+            glsl.push('\n');
+        }
+
+        match self.format {
+            IsfFormat::Isf => self.push_isf_declarations(&mut glsl, index),
+            IsfFormat::Shadertoy => glsl.push_str(SHADERTOY_DECLARATIONS),
+        }
+
+        glsl.push_str("\n#line 1\n");
+
+        match self.format {
+            IsfFormat::Isf => {
+                let frag_color = Regex::new(r"\bgl_FragColor\b").unwrap();
+                glsl.push_str(&frag_color.replace_all(&self.body, "frag_color"));
+            }
+            IsfFormat::Shadertoy => {
+                // textures are stored top row first but Shadertoy samples them bottom row first
+                let channel_sample =
+                    Regex::new(r"\b(texture|textureLod)\s*\(\s*(iChannel[0-3])\b").unwrap();
+                glsl.push_str(&channel_sample.replace_all(&self.body, "shadertoy_$1($2"));
+                glsl.push_str(SHADERTOY_MAIN);
+            }
+        }
+
+        glsl
+    }
+
+    fn push_isf_declarations(&self, glsl: &mut String, index: usize) {
+        let mut images = self.inputs.iter().filter(|i| i.is_image());
+
+        // the first image input is the camera, the others are inputs of the chain
+        if let Some(camera) = images.next() {
+            glsl.push_str(&format!("#define {} video\n", camera.name));
+        }
+        for (i, image) in images.enumerate() {
+            glsl.push_str(&format!(
+                "layout(set = 0, binding = EFFECT_USER_BINDING + {}) uniform texture2D {};\n",
+                i + 1,
+                image.name
+            ));
+        }
+
+        for input in self.inputs.iter() {
+            if let Some(IsfValue::Bool(_)) = input.default {
+                glsl.push_str(&format!(
+                    "#define {} ({} != 0u)\n",
+                    input.name,
+                    input.field_name()
+                ));
+            }
+        }
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            let target = match &pass.target {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let texture = if i == index && pass.persistent {
+                "previous_frame"
+            } else {
+                "tex"
+            };
+            glsl.push_str(&format!("#define {} {}\n", target, texture));
+        }
+
+        glsl.push_str(&format!("#define PASSINDEX {}\n", index));
+        glsl.push_str(ISF_DECLARATIONS);
+    }
+}
+
+const ISF_DECLARATIONS: &str = "#define TIME time
+#define TIMEDELTA delta_time
+#define FRAMEINDEX int(frame)
+#define RENDERSIZE render_size
+#define isf_FragNormCoord vec2(tex_coords.x, 1.0 - tex_coords.y)
+#define vv_FragNormCoord isf_FragNormCoord
+
+#define IMG_SIZE(img) vec2(textureSize(sampler2D(img, tex_sampler), 0))
+#define IMG_NORM_PIXEL(img, coord) texture(sampler2D(img, tex_sampler), vec2((coord).x, 1.0 - (coord).y))
+#define IMG_PIXEL(img, coord) IMG_NORM_PIXEL(img, (coord) / IMG_SIZE(img))
+#define IMG_THIS_NORM_PIXEL(img) IMG_NORM_PIXEL(img, isf_FragNormCoord)
+#define IMG_THIS_PIXEL(img) IMG_THIS_NORM_PIXEL(img)
+";
+
+const SHADERTOY_DECLARATIONS: &str = "#define iResolution vec3(render_size, 1.0)
+#define iTime time
+#define iTimeDelta delta_time
+#define iFrame int(frame)
+#define shadertoy_mouse (vec2(mouse.x, 1.0 - mouse.y) * render_size)
+#define iMouse vec4(shadertoy_mouse, (mouse_buttons & MOUSE_LEFT) != 0u ? shadertoy_mouse : -shadertoy_mouse)
+
+#define iChannel0 sampler2D(video, tex_sampler)
+#define iChannel1 sampler2D(tex, tex_sampler)
+#define iChannelResolution vec3[4](vec3(video_resolution, 1.0), iResolution, vec3(0.0), vec3(0.0))
+
+#define shadertoy_texture(channel, uv) texture(channel, vec2((uv).x, 1.0 - (uv).y))
+#define shadertoy_textureLod(channel, uv, lod) textureLod(channel, vec2((uv).x, 1.0 - (uv).y), lod)
+";
+
+const SHADERTOY_MAIN: &str = "
+
+void main() {
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    // Shadertoy's origin is at the bottom left
+    mainImage(color, vec2(tex_coords.x, 1.0 - tex_coords.y) * render_size);
+    frag_color = vec4(color.rgb, 1.0);
+}
+";
+
+/// Splits an ISF file into its JSON header and its body, with the header replaced by blank lines.
+fn split_header(source: &str) -> Option<(&str, String)> {
+    let trimmed = source.trim_start();
+    let comment = trimmed.strip_prefix("/*")?;
+    if !comment.trim_start().starts_with('{') {
+        return None;
+    }
+
+    let end = comment.find("*/")?;
+    let header = &comment[..end];
+
+    let header_end = source.len() - trimmed.len() + 2 + end + 2;
+    let lines = source[..header_end].matches('\n').count();
+    let body = "\n".repeat(lines) + &source[header_end..];
+
+    Some((header, body))
+}
+
+/// Checks that every pass target the body reads can be provided by the chain, see `IsfEffect`.
+fn check_target_reads(passes: &[IsfPass], body: &str) -> Result<(), IsfError> {
+    let blocks = pass_blocks(body);
+
+    for (index, pass) in passes.iter().enumerate() {
+        let target = match &pass.target {
+            Some(target) => target,
+            None => continue,
+        };
+
+        let readable = |reader: usize| reader == index + 1 || (reader == index && pass.persistent);
+
+        let read = Regex::new(&format!(r"\b{}\b", regex::escape(target))).unwrap();
+        for found in read.find_iter(body) {
+            let reader = blocks
+                .iter()
+                .filter(|(_, block)| block.contains(&found.start()))
+                .min_by_key(|(_, block)| block.len())
+                .map(|(reader, _)| *reader);
+
+            let supported = match reader {
+                Some(reader) => readable(reader),
+                None => (0..passes.len()).any(readable),
+            };
+            if !supported {
+                return Err(IsfError::UnsupportedTargetRead {
+                    target: target.clone(),
+                    pass: reader,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The ranges of the body inside `if (PASSINDEX == n) { ... }` blocks, along with n.
+fn pass_blocks(body: &str) -> Vec<(usize, Range<usize>)> {
+    let condition = Regex::new(r"\bPASSINDEX\s*==\s*(\d+)").unwrap();
+    let mut blocks = vec![];
+
+    for captures in condition.captures_iter(body) {
+        let pass = match captures[1].parse() {
+            Ok(pass) => pass,
+            Err(_) => continue,
+        };
+
+        // only a block that directly follows the condition belongs to it
+        let after = captures.get(0).unwrap().end();
+        let start = match body[after..].find('{') {
+            Some(offset) => after + offset,
+            None => continue,
+        };
+        if body[after..start].contains(|c: char| !c.is_whitespace() && c != ')') {
+            continue;
+        }
+
+        let mut depth = 0;
+        for (offset, c) in body[start..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        blocks.push((pass, start..start + offset + 1));
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    blocks
+}
+
+/// Parses an entry of `INPUTS`, skipping entries without a name.
+fn parse_input(input: &Value) -> Result<Option<IsfInput>, IsfError> {
+    let name = match input.get("NAME").and_then(Value::as_str) {
+        Some(name) => name.to_string(),
+        None => return Ok(None),
+    };
+    let ty = input.get("TYPE").and_then(Value::as_str).unwrap_or("");
+
+    let zero = match ty {
+        "float" => IsfValue::Float(0.0),
+        "long" => IsfValue::Long(0),
+        "bool" | "event" => IsfValue::Bool(false),
+        "color" => IsfValue::Color([0.0, 0.0, 0.0, 1.0]),
+        "point2D" => IsfValue::Point2D([0.0, 0.0]),
+        "image" => {
+            return Ok(Some(IsfInput {
+                name,
+                label: None,
+                default: None,
+                min: None,
+                max: None,
+            }))
+        }
+        _ => {
+            return Err(IsfError::UnsupportedInput {
+                name,
+                ty: ty.to_string(),
+            })
+        }
+    };
+
+    let value = |key: &str| -> Result<Option<IsfValue>, IsfError> {
+        let value = match input.get(key) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let parsed = match zero {
+            IsfValue::Float(_) => value.as_f64().map(|v| IsfValue::Float(v as f32)),
+            IsfValue::Long(_) => value.as_f64().map(|v| IsfValue::Long(v as i32)),
+            IsfValue::Bool(_) => Some(IsfValue::Bool(is_truthy(value))),
+            IsfValue::Color(_) => floats(value).map(IsfValue::Color),
+            IsfValue::Point2D(_) => floats(value).map(IsfValue::Point2D),
+        };
+
+        parsed.map(Some).ok_or_else(|| IsfError::InvalidInput {
+            name: name.clone(),
+            message: format!("{} is not a {}", key, ty),
+        })
+    };
+
+    Ok(Some(IsfInput {
+        label: input
+            .get("LABEL")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        default: Some(value("DEFAULT")?.unwrap_or(zero)),
+        min: value("MIN")?,
+        max: value("MAX")?,
+        name,
+    }))
+}
+
+fn floats<const N: usize>(value: &Value) -> Option<[f32; N]> {
+    let values = value.as_array()?;
+    if values.len() != N {
+        return None;
+    }
+
+    let mut floats = [0.0; N];
+    for (float, value) in floats.iter_mut().zip(values) {
+        *float = value.as_f64()? as f32;
+    }
+    Some(floats)
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().map_or(false, |n| n != 0.0),
+        Value::String(s) => s == "true" || s == "1",
+        _ => false,
+    }
+}
+
+/// The `IsfInputs` block of an imported effect: the values of its inputs, starting at their
+/// defaults. Only effects that `has_parameters` need one; bind it to the chain under
+/// `BLOCK_NAME`.
+pub struct IsfParameters {
+    layout: UniformLayout,
+    names: Vec<String>,
+    values: Vec<IsfValue>,
+    pub buffer: wgpu::Buffer,
+    uploaded: Vec<u8>,
+}
+
+impl IsfParameters {
+    pub fn new(device: &wgpu::Device, effect: &IsfEffect) -> Self {
+        let layout = effect.layout();
+        let inputs = effect.inputs.iter().filter(|i| !i.is_image());
+        let names = inputs.clone().map(|i| i.name.clone()).collect();
+        let values: Vec<IsfValue> = inputs.filter_map(|i| i.default).collect();
+
+        let bytes = std140_bytes(&layout, &values);
+        let buffer = device.create_buffer_init(&wgpu::BufferInitDescriptor {
+            label: Some("isf-inputs"),
+            contents: &bytes,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            layout,
+            names,
+            values,
+            buffer,
+            uploaded: bytes,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<IsfValue> {
+        let index = self.names.iter().position(|n| n == name)?;
+        Some(self.values[index])
+    }
+
+    /// Sets an input, which must be given a value of the type it was declared with.
+    pub fn set(&mut self, name: &str, value: IsfValue) -> Result<(), IsfError> {
+        let index = self
+            .names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| IsfError::UnknownInput(name.to_string()))?;
+
+        if self.values[index].uniform_type() != value.uniform_type() {
+            return Err(IsfError::InvalidInput {
+                name: name.to_string(),
+                message: format!("{:?} does not match its type", value),
+            });
+        }
+
+        self.values[index] = value;
+        Ok(())
+    }
+
+    /// Takes the values of the inputs `previous` also has, e.g. when the effect is reloaded.
+    /// Inputs whose type changed keep their defaults.
+    pub fn inherit(&mut self, previous: &IsfParameters) {
+        for (name, value) in previous.names.iter().zip(previous.values.iter()) {
+            let _ = self.set(name, *value);
+        }
+    }
+
+    pub fn binding(&self) -> wgpu::BufferBinding {
+        wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: None,
+        }
+    }
+
+    /// Writes the values to the buffer if they changed since the last upload.
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        let bytes = std140_bytes(&self.layout, &self.values);
+        if bytes == self.uploaded {
+            return;
+        }
+
+        queue.write_buffer(&self.buffer, 0, &bytes);
+        self.uploaded = bytes;
+    }
+}
+
+fn std140_bytes(layout: &UniformLayout, values: &[IsfValue]) -> Vec<u8> {
+    let mut writer = layout.writer();
+    for value in values.iter() {
+        value.write(&mut writer);
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn isf(header: Value, body: &str) -> String {
+        format!(
+            "/*{}*/\n{}",
+            serde_json::to_string_pretty(&header).unwrap(),
+            body
+        )
+    }
+
+    #[test]
+    fn split_header_keeps_line_numbers() {
+        let source = "\n/*{\n  \"INPUTS\": []\n}*/\nvoid main() {}\n";
+        let (header, body) = split_header(source).unwrap();
+
+        assert_eq!(header, "{\n  \"INPUTS\": []\n}");
+        assert_eq!(body.lines().count(), source.lines().count());
+        assert_eq!(body.lines().nth(4), Some("void main() {}"));
+        assert!(body.lines().take(4).all(str::is_empty));
+    }
+
+    #[test]
+    fn split_header_ignores_other_comments() {
+        assert!(split_header("/* not json */\nvoid main() {}").is_none());
+        assert!(split_header("void main() {}").is_none());
+    }
+
+    #[test]
+    fn parses_bool_color_and_point_inputs() {
+        let flag = parse_input(&json!({"NAME": "flag", "TYPE": "bool", "DEFAULT": 1}))
+            .unwrap()
+            .unwrap();
+        assert_eq!(flag.default, Some(IsfValue::Bool(true)));
+
+        let event = parse_input(&json!({"NAME": "bang", "TYPE": "event"}))
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.default, Some(IsfValue::Bool(false)));
+
+        let tint = parse_input(&json!({
+            "NAME": "tint",
+            "TYPE": "color",
+            "LABEL": "Tint",
+            "DEFAULT": [1.0, 0.5, 0.0, 1.0]
+        }))
+        .unwrap()
+        .unwrap();
+        assert_eq!(tint.label.as_deref(), Some("Tint"));
+        assert_eq!(tint.default, Some(IsfValue::Color([1.0, 0.5, 0.0, 1.0])));
+
+        let center = parse_input(&json!({
+            "NAME": "center",
+            "TYPE": "point2D",
+            "MIN": [0, 0],
+            "MAX": [1, 1]
+        }))
+        .unwrap()
+        .unwrap();
+        assert_eq!(center.default, Some(IsfValue::Point2D([0.0, 0.0])));
+        assert_eq!(center.min, Some(IsfValue::Point2D([0.0, 0.0])));
+        assert_eq!(center.max, Some(IsfValue::Point2D([1.0, 1.0])));
+    }
+
+    #[test]
+    fn rejects_invalid_inputs() {
+        let short_color = parse_input(&json!({"NAME": "tint", "TYPE": "color", "DEFAULT": [1, 0]}));
+        assert!(matches!(short_color, Err(IsfError::InvalidInput { .. })));
+
+        let audio = parse_input(&json!({"NAME": "sound", "TYPE": "audio"}));
+        assert!(matches!(audio, Err(IsfError::UnsupportedInput { .. })));
+
+        assert!(matches!(parse_input(&json!({"TYPE": "float"})), Ok(None)));
+    }
+
+    #[test]
+    fn rejects_duplicate_inputs() {
+        let source = isf(
+            json!({"INPUTS": [
+                {"NAME": "amount", "TYPE": "float"},
+                {"NAME": "amount", "TYPE": "long"}
+            ]}),
+            "void main() {}",
+        );
+
+        match IsfEffect::parse(&source) {
+            Err(IsfError::InvalidInput { name, .. }) => assert_eq!(name, "amount"),
+            other => panic!("expected a duplicate input error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn maps_pass_targets_onto_the_chain() {
+        let source = isf(
+            json!({"PASSES": [
+                {"TARGET": "trail", "PERSISTENT": true},
+                {"TARGET": "blurred"},
+                {}
+            ]}),
+            "void main() {
+    if (PASSINDEX == 0) {
+        gl_FragColor = mix(IMG_THIS_PIXEL(video), IMG_THIS_PIXEL(trail), 0.9);
+    } else if (PASSINDEX == 1) {
+        gl_FragColor = IMG_THIS_PIXEL(trail);
+    } else {
+        gl_FragColor = IMG_THIS_PIXEL(blurred);
+    }
+}",
+        );
+        let effect = IsfEffect::parse(&source).unwrap();
+
+        assert_eq!(effect.passes.len(), 3);
+        assert_eq!(effect.history_frames(0), 1);
+        assert_eq!(effect.history_frames(1), 0);
+
+        let first = effect.pass_glsl(0);
+        assert!(first.contains("#define trail previous_frame\n"));
+        assert!(first.contains("#define PASSINDEX 0\n"));
+        assert!(first.contains("frag_color = mix("));
+
+        let second = effect.pass_glsl(1);
+        assert!(second.contains("#define trail tex\n"));
+        assert!(second.contains("#define PASSINDEX 1\n"));
+
+        assert!(effect.pass_glsl(2).contains("#define blurred tex\n"));
+    }
+
+    #[test]
+    fn rejects_target_reads_the_chain_cannot_provide() {
+        // two passes after the target
+        let skipped = isf(
+            json!({"PASSES": [{"TARGET": "first"}, {"TARGET": "second"}, {}]}),
+            "void main() {
+    if (PASSINDEX == 2) {
+        gl_FragColor = IMG_THIS_PIXEL(first);
+    }
+}",
+        );
+        match IsfEffect::parse(&skipped) {
+            Err(IsfError::UnsupportedTargetRead { target, pass }) => {
+                assert_eq!(target, "first");
+                assert_eq!(pass, Some(2));
+            }
+            other => panic!("expected a target read error, got {:?}", other),
+        }
+
+        // a later pass's persistent buffer, fed back to an earlier pass
+        let feedback = isf(
+            json!({"PASSES": [{"TARGET": "first"}, {"TARGET": "feedback", "PERSISTENT": true}]}),
+            "void main() {
+    if (PASSINDEX == 0) {
+        gl_FragColor = IMG_THIS_PIXEL(feedback);
+    } else {
+        gl_FragColor = IMG_THIS_PIXEL(first);
+    }
+}",
+        );
+        assert!(matches!(
+            IsfEffect::parse(&feedback),
+            Err(IsfError::UnsupportedTargetRead { pass: Some(0), .. })
+        ));
+
+        // the last pass's target outside a PASSINDEX block, which no pass can read
+        let last = isf(
+            json!({"PASSES": [{}, {"TARGET": "last"}]}),
+            "void main() { gl_FragColor = IMG_THIS_PIXEL(last); }",
+        );
+        assert!(matches!(
+            IsfEffect::parse(&last),
+            Err(IsfError::UnsupportedTargetRead { pass: None, .. })
+        ));
+    }
+
+    #[test]
+    fn sizes_are_the_chain_render_size() {
+        let effect = IsfEffect::parse(&isf(json!({}), "void main() {}")).unwrap();
+        assert!(effect
+            .pass_glsl(0)
+            .contains("#define RENDERSIZE render_size\n"));
+
+        let shadertoy = IsfEffect::parse(
+            "void mainImage(out vec4 color, in vec2 coord) { color = vec4(coord / iResolution.xy, 0.0, 1.0); }",
+        )
+        .unwrap();
+        let glsl = shadertoy.pass_glsl(0);
+        assert!(glsl.contains("#define iResolution vec3(render_size, 1.0)\n"));
+        assert!(glsl
+            .contains("mainImage(color, vec2(tex_coords.x, 1.0 - tex_coords.y) * render_size);"));
+        assert!(!glsl.contains("* resolution"));
+    }
+
+    #[test]
+    fn declares_inputs_only_when_there_are_any() {
+        let source = isf(
+            json!({"INPUTS": [
+                {"NAME": "inputImage", "TYPE": "image"},
+                {"NAME": "invert", "TYPE": "bool"}
+            ]}),
+            "void main() {}",
+        );
+        let effect = IsfEffect::parse(&source).unwrap();
+        assert!(effect.has_parameters());
+
+        let glsl = effect.pass_glsl(0);
+        assert!(glsl.contains(BLOCK_NAME));
+        assert!(glsl.contains("#define inputImage video\n"));
+        assert!(glsl.contains("#define invert (isf_bool_invert != 0u)\n"));
+
+        let shadertoy = IsfEffect::parse(
+            "void mainImage(out vec4 color, in vec2 coord) { color = vec4(1.0); }",
+        )
+        .unwrap();
+        assert_eq!(shadertoy.format, IsfFormat::Shadertoy);
+        assert!(!shadertoy.has_parameters());
+        assert!(!shadertoy.pass_glsl(0).contains(BLOCK_NAME));
+
+        assert!(matches!(
+            IsfEffect::parse("void main() {}"),
+            Err(IsfError::MissingMainImage)
+        ));
+    }
+}
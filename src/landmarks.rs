@@ -0,0 +1,168 @@
+use mediapipe::holistic::HolisticDetection;
+use nannou::prelude::*;
+
+/// The name shaders include the generated `Landmarks` buffer declaration by.
+pub const GLSL_INCLUDE_NAME: &str = "landmarks.glsl";
+
+/// The block name of the buffer in GLSL, which effect passes bind it by.
+pub const LANDMARKS_BLOCK_NAME: &str = "Landmarks";
+
+// Capacity of each landmark group. Face meshes have 468 landmarks, or 478 with irises.
+pub const FACE_LANDMARKS: usize = 478;
+pub const POSE_LANDMARKS: usize = 33;
+pub const HAND_LANDMARKS: usize = 21;
+
+pub const FACE_OFFSET: usize = 0;
+pub const POSE_OFFSET: usize = FACE_OFFSET + FACE_LANDMARKS;
+pub const LEFT_HAND_OFFSET: usize = POSE_OFFSET + POSE_LANDMARKS;
+pub const RIGHT_HAND_OFFSET: usize = LEFT_HAND_OFFSET + HAND_LANDMARKS;
+pub const LANDMARK_COUNT: usize = RIGHT_HAND_OFFSET + HAND_LANDMARKS;
+
+/// The size of the buffer: a uvec4 of counts followed by a vec4 per landmark.
+pub const LANDMARK_BUFFER_SIZE: u64 = (16 + LANDMARK_COUNT * 16) as u64;
+
+/// The latest holistic detection packed into a storage buffer for effect shaders.
+///
+/// The std430 layout is:
+/// - `uvec4 counts`: the number of face, pose, left hand and right hand landmarks, 0 if not detected
+/// - `vec4 landmarks[LANDMARK_COUNT]`: x, y, z and visibility of every landmark, grouped at
+///   `FACE_OFFSET`, `POSE_OFFSET`, `LEFT_HAND_OFFSET` and `RIGHT_HAND_OFFSET`
+///
+/// x and y are in texture coordinates, matching `tex_coords` in the effect shaders.
+pub struct LandmarkBuffer {
+    pub buffer: wgpu::Buffer,
+    uploaded: Vec<u8>,
+}
+
+impl LandmarkBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bytes = vec![0; LANDMARK_BUFFER_SIZE as usize];
+        let buffer = device.create_buffer_init(&wgpu::BufferInitDescriptor {
+            label: Some("landmark-buffer"),
+            contents: &bytes,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            buffer,
+            uploaded: bytes,
+        }
+    }
+
+    /// Packs the detection and writes it to the buffer if it changed since the last upload.
+    pub fn update(&mut self, queue: &wgpu::Queue, detection: Option<&HolisticDetection>) {
+        let bytes = pack_detection(detection);
+        if bytes == self.uploaded {
+            return;
+        }
+
+        queue.write_buffer(&self.buffer, 0, &bytes);
+        self.uploaded = bytes;
+    }
+}
+
+fn pack_detection(detection: Option<&HolisticDetection>) -> Vec<u8> {
+    let mut counts = [0u32; 4];
+    let mut landmarks = vec![[0.0f32; 4]; LANDMARK_COUNT];
+
+    if let Some(detection) = detection {
+        if let Some(face) = &detection.face {
+            counts[0] = pack_group(&face.data, &mut landmarks, FACE_OFFSET, FACE_LANDMARKS);
+        }
+
+        if let Some(pose) = &detection.pose {
+            counts[1] = pack_group(&pose.data, &mut landmarks, POSE_OFFSET, POSE_LANDMARKS);
+        }
+
+        if let Some(left_hand) = &detection.left_hand {
+            counts[2] = pack_group(
+                &left_hand.data,
+                &mut landmarks,
+                LEFT_HAND_OFFSET,
+                HAND_LANDMARKS,
+            );
+        }
+
+        if let Some(right_hand) = &detection.right_hand {
+            counts[3] = pack_group(
+                &right_hand.data,
+                &mut landmarks,
+                RIGHT_HAND_OFFSET,
+                HAND_LANDMARKS,
+            );
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(LANDMARK_BUFFER_SIZE as usize);
+    counts
+        .iter()
+        .for_each(|c| bytes.extend_from_slice(&c.to_ne_bytes()));
+    landmarks
+        .iter()
+        .flatten()
+        .for_each(|f| bytes.extend_from_slice(&f.to_ne_bytes()));
+    bytes
+}
+
+/// Copies up to `capacity` landmarks into place, returning how many were copied.
+fn pack_group(
+    group: &[mediapipe::Landmark],
+    landmarks: &mut [[f32; 4]],
+    offset: usize,
+    capacity: usize,
+) -> u32 {
+    let count = group.len().min(capacity);
+
+    for (i, landmark) in group.iter().take(count).enumerate() {
+        landmarks[offset + i] = [landmark.x, landmark.y, landmark.z, landmark.visibility];
+    }
+
+    count as u32
+}
+
+/// The contents of the `landmarks.glsl` include, generated from the layout above.
+/// Define LANDMARKS_BINDING before including it if the buffer is not at binding 0.
+pub fn glsl_include() -> String {
+    format!(
+        "// Generated from landmarks::LandmarkBuffer, do not edit.
+
+#ifndef LANDMARKS_GLSL
+#define LANDMARKS_GLSL
+
+#ifndef LANDMARKS_BINDING
+#define LANDMARKS_BINDING 0
+#endif
+
+#define FACE_OFFSET {}
+#define POSE_OFFSET {}
+#define LEFT_HAND_OFFSET {}
+#define RIGHT_HAND_OFFSET {}
+#define LANDMARK_COUNT {}
+
+layout(std430, set = 0, binding = LANDMARKS_BINDING) readonly buffer {} {{
+    // face, pose, left hand and right hand landmark counts
+    uvec4 landmark_counts;
+    // x, y, z, visibility
+    vec4 landmarks[LANDMARK_COUNT];
+}};
+
+bool has_face() {{ return landmark_counts.x > 0u; }}
+bool has_pose() {{ return landmark_counts.y > 0u; }}
+bool has_left_hand() {{ return landmark_counts.z > 0u; }}
+bool has_right_hand() {{ return landmark_counts.w > 0u; }}
+
+vec4 face_landmark(int i) {{ return landmarks[FACE_OFFSET + i]; }}
+vec4 pose_landmark(int i) {{ return landmarks[POSE_OFFSET + i]; }}
+vec4 left_hand_landmark(int i) {{ return landmarks[LEFT_HAND_OFFSET + i]; }}
+vec4 right_hand_landmark(int i) {{ return landmarks[RIGHT_HAND_OFFSET + i]; }}
+
+#endif
+",
+        FACE_OFFSET,
+        POSE_OFFSET,
+        LEFT_HAND_OFFSET,
+        RIGHT_HAND_OFFSET,
+        LANDMARK_COUNT,
+        LANDMARKS_BLOCK_NAME
+    )
+}
@@ -1,9 +1,26 @@
+use std::collections::BTreeMap;
+
 use nannou::prelude::*;
 
+mod compositor;
+mod compute;
+mod detector;
+mod effect_chain;
+mod effects;
 mod faces;
+mod frame_history;
 mod holistic_detector;
+mod isf;
+mod landmarks;
+mod prediction;
+mod reflect;
 mod render;
 mod segmentation;
+mod shader_cache;
+mod shader_check;
+mod shader_watcher;
+mod smoothing;
+mod std140;
 mod texture;
 mod uniforms;
 mod util;
@@ -13,13 +30,35 @@ mod webcam;
 use crate::holistic_detector::*;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some(shader_check::COMMAND) {
+        let effect_dirs: Vec<_> = args[1..].iter().map(std::path::PathBuf::from).collect();
+        let passed = shader_check::run(&effect_dirs);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
     nannou::app(model).update(update).run();
 }
 
 struct Model {
+    compositor: compositor::Compositor,
+    /// The parameters of the built-in effects, which any pass of the chain can use.
+    effect_library: effects::EffectLibrary,
+    /// Whether to show the frame the latest detection came from instead of the newest frame,
+    /// so the overlays line up with the video at the cost of latency.
+    delay_video: bool,
     detector: HolisticDetector,
+    effect_chain: effect_chain::EffectChain,
+    frame_history: frame_history::FrameHistory,
+    /// The inputs of the chain's imported ISF effect, if it has any.
+    isf_parameters: Option<isf::IsfParameters>,
+    landmarks: landmarks::LandmarkBuffer,
+    sampler: wgpu::Sampler,
+    /// The latest reload error of each shader that's currently broken, by filename.
+    shader_errors: BTreeMap<String, util::ShaderError>,
+    shader_watcher: shader_watcher::ShaderWatcher,
     size: Vec2,
-    video_texture_reshaper: wgpu::TextureReshaper,
+    uniforms: uniforms::UniformBuffer<uniforms::Uniforms>,
     video_size: Vec2,
     webcam_capture: webcam::WebcamCapture,
     // segmentor: Segmentor,
@@ -28,12 +67,17 @@ struct Model {
 const WIDTH: u32 = 1920;
 const HEIGHT: u32 = 1080;
 
+/// The effect the chain runs. ISF and Shadertoy effects (`.fs`) can have several passes.
+const EFFECT_SHADER: &str = "default.frag";
+
 fn model(app: &App) -> Model {
     // create window
     let main_window_id = app
         .new_window()
         .size(WIDTH, HEIGHT)
         .view(view)
+        .key_pressed(key_pressed)
+        .resized(resized)
         .build()
         .unwrap();
 
@@ -54,43 +98,408 @@ fn model(app: &App) -> Model {
 
     let video_texture = &webcam_capture.video_capture.as_ref().unwrap().video_texture;
 
-    let video_texture_reshaper =
-        render::create_texture_reshaper(&device, &video_texture, 1, sample_count);
+    let uniforms = uniforms::UniformBuffer::new(&device, uniforms::Uniforms::new(size, video_size));
+    let landmarks = landmarks::LandmarkBuffer::new(&device);
+    let sampler = wgpu::SamplerBuilder::new().build(&device);
+    let effect_library = effects::EffectLibrary::with_defaults(&device);
+
+    let vs_mod = util::compile_shader(app, &device, "default.vert", shaderc::ShaderKind::Vertex)
+        .expect("failed to compile vertex shader");
+    let (passes, isf_parameters) = util::load_effect_or_fallback(
+        app,
+        &device,
+        EFFECT_SHADER,
+        util::FallbackShader::Passthrough,
+    );
+
+    let resources = effect_chain::EffectResources {
+        source: video_texture,
+        inputs: vec![],
+        landmarks: &landmarks,
+        storage_buffers: vec![],
+        uniform_buffers: uniform_buffers(&effect_library, &isf_parameters),
+        sampler: &sampler,
+        uniforms: Some(uniforms.binding()),
+    };
+
+    let effect_chain = match effect_chain::EffectChain::new(
+        &device,
+        vs_mod,
+        passes,
+        &resources,
+        video_size.x as u32,
+        video_size.y as u32,
+        sample_count,
+    ) {
+        Ok(effect_chain) => effect_chain,
+        Err(e) => {
+            println!("Error creating effect chain, showing the video instead: {}", e);
+            let vs_mod =
+                util::compile_shader(app, &device, "default.vert", shaderc::ShaderKind::Vertex)
+                    .expect("failed to compile vertex shader");
+            let fs = util::compile_fallback_shader(&device, util::FallbackShader::Passthrough);
+
+            effect_chain::EffectChain::new(
+                &device,
+                vs_mod,
+                vec![effect_chain::EffectPassDescriptor::new("passthrough", fs)],
+                &resources,
+                video_size.x as u32,
+                video_size.y as u32,
+                sample_count,
+            )
+            .expect("failed to create passthrough effect chain")
+        }
+    };
+
+    let mut compositor = compositor::Compositor::new(
+        &device,
+        util::compile_shader(app, &device, "default.vert", shaderc::ShaderKind::Vertex)
+            .expect("failed to compile vertex shader"),
+        util::compile_shader(app, &device, "layer.frag", shaderc::ShaderKind::Fragment)
+            .expect("failed to compile layer shader"),
+        width,
+        height,
+        sample_count,
+    );
+    compositor
+        .add_texture_layer(
+            &device,
+            "effects",
+            effect_chain.output_texture(),
+            render::BlendMode::Replace,
+        )
+        .unwrap();
+    compositor
+        .add_draw_layer(&device, "landmarks", render::BlendMode::Premultiplied)
+        .unwrap();
 
     // let segmentor = Segmentor::new(&device, video_size, sample_count);
 
     println!("creating model");
     Model {
-        detector: HolisticDetector::new(video_size),
+        compositor,
+        effect_library,
+        delay_video: false,
+        detector: HolisticDetector::new(),
+        effect_chain,
+        frame_history: frame_history::FrameHistory::new(frame_history::DEFAULT_CAPACITY),
+        isf_parameters,
+        landmarks,
+        sampler,
+        shader_errors: BTreeMap::new(),
+        shader_watcher: shader_watcher::ShaderWatcher::new(util::shaders_path(app)),
         size,
-        video_texture_reshaper,
+        uniforms,
         video_size,
         webcam_capture,
         // segmentor,
     }
 }
 
-fn update(app: &App, model: &mut Model, _update: Update) {
+fn update(app: &App, model: &mut Model, update: Update) {
     // println!("update");
     let window = app.main_window();
     let device = window.device();
 
     model.webcam_capture.update();
 
-    if let Some(frame) = model.webcam_capture.get_frame_ref() {
-        model.detector.update(frame);
+    model
+        .uniforms
+        .data
+        .update_time(app.time, update.since_last.as_secs_f32());
+    model.uniforms.data.update_mouse(&app.mouse, model.size);
+    let (render_width, render_height) = model.effect_chain.size();
+    model.uniforms.data.render_size = [render_width as f32, render_height as f32];
+
+    // The encoder we'll use to encode the compute pass and render pass.
+    let desc = wgpu::CommandEncoderDescriptor {
+        label: Some("encoder"),
+    };
+    let mut encoder = device.create_command_encoder(&desc);
+
+    reload_shaders(app, model, device, &mut encoder);
+
+    let tag = model.webcam_capture.get_frame_tag();
+    if let (Some(frame), Some(tag)) = (model.webcam_capture.get_frame_ref(), tag) {
+        model.detector.update(tag, frame);
+
+        if model.delay_video {
+            model.frame_history.push(tag, frame);
+            let shown = model
+                .detector
+                .latest_tag()
+                .and_then(|t| model.frame_history.get(t.sequence))
+                .unwrap_or(frame);
+            model
+                .webcam_capture
+                .upload_frame(device, &mut encoder, shown);
+        } else {
+            model.webcam_capture.update_texture(device, &mut encoder);
+        }
+        // model.segmentor.update(device, &mut encoder, tag, frame);
+    }
+
+    // Render every update rather than only when a frame arrives, so predicted landmarks move
+    // at the display rate. Delayed video shows the detection's own frame, so isn't predicted.
+    let render_time = match model.detector.latest_tag() {
+        Some(tag) if model.delay_video => tag.captured,
+        _ => std::time::Instant::now(),
+    };
+    model.detector.predict(render_time);
+    model.uniforms.data.update_detections(&model.detector);
+
+    model.uniforms.update(window.queue());
+    model.effect_library.update(window.queue());
+    if let Some(parameters) = model.isf_parameters.as_mut() {
+        parameters.update(window.queue());
+    }
+    model
+        .landmarks
+        .update(window.queue(), model.detector.latest());
+    model.effect_chain.render(&mut encoder);
+
+    let overlay = Draw::new();
+    model
+        .detector
+        .draw_detections(&overlay, &model.video_size, &model.size);
+    model
+        .compositor
+        .draw(device, &mut encoder, "landmarks", &overlay);
+
+    model.compositor.update(window.queue());
+    model.compositor.render(&mut encoder);
+
+    // submit encoded command buffer
+    window.queue().submit(Some(encoder.finish()));
+}
+
+/// Recompiles changed effect shaders and rebuilds their pipelines.
+/// A change to the shader library recompiles every effect, since any of them may include it.
+/// If compilation fails the last good pipeline stays in use and the error is kept for display
+/// until that shader reloads successfully. Reloading clears the chain's history, since the
+/// previous frames were rendered by the old shaders.
+fn reload_shaders(
+    app: &App,
+    model: &mut Model,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+) {
+    let changed = model.shader_watcher.changed_files();
+    let library_prefix = format!("{}/", util::SHADER_LIBRARY_DIR);
 
-        // The encoder we'll use to encode the compute pass and render pass.
-        let desc = wgpu::CommandEncoderDescriptor {
-            label: Some("encoder"),
-        };
-        let mut encoder = device.create_command_encoder(&desc);
+    let to_reload = if changed.iter().any(|f| f.starts_with(&library_prefix)) {
+        model.effect_chain.shader_names()
+    } else {
+        changed
+            .into_iter()
+            .filter(|f| model.effect_chain.uses_shader(f))
+            .collect()
+    };
 
-        model.webcam_capture.update_texture(device, &mut encoder);
-        // model.segmentor.update(device, &mut encoder, frame);
+    for filename in to_reload {
+        let mut error = None;
+        if util::is_isf(&filename) {
+            if let Err(e) = reload_isf_effect(app, model, device, encoder, &filename) {
+                println!("Error reloading shader {}: {}", filename, e);
+                error = Some(e);
+            }
+        } else {
+            // each variant is compiled for the kind of pass and with the defines it was
+            // created with
+            for variant in model.effect_chain.shader_variants(&filename) {
+                if let Err(e) = reload_variant(app, model, device, encoder, &filename, &variant) {
+                    println!("Error reloading shader {}: {}", filename, e);
+                    error = Some(e);
+                }
+            }
+        }
 
-        // submit encoded command buffer
-        window.queue().submit(Some(encoder.finish()));
+        match error {
+            Some(e) => {
+                model.shader_errors.insert(filename, e);
+            }
+            None => {
+                model.shader_errors.remove(&filename);
+            }
+        }
+    }
+}
+
+fn reload_variant(
+    app: &App,
+    model: &mut Model,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    filename: &str,
+    variant: &effect_chain::ShaderVariant,
+) -> Result<(), util::ShaderError> {
+    let shader = util::compile_effect_shader(
+        app,
+        device,
+        filename,
+        variant.kind,
+        &util::borrow_defines(&variant.defines),
+    )?;
+
+    let resources = effect_chain::EffectResources {
+        source: &model
+            .webcam_capture
+            .video_capture
+            .as_ref()
+            .unwrap()
+            .video_texture,
+        inputs: vec![],
+        landmarks: &model.landmarks,
+        storage_buffers: vec![],
+        uniform_buffers: uniform_buffers(&model.effect_library, &model.isf_parameters),
+        sampler: &model.sampler,
+        uniforms: Some(model.uniforms.binding()),
+    };
+
+    model
+        .effect_chain
+        .reload_shader(device, filename, variant, shader, &resources)
+        .and_then(|rebuilt| {
+            if !rebuilt {
+                return Ok(());
+            }
+            // the chain's output texture was replaced
+            model.compositor.set_layer_texture(
+                device,
+                "effects",
+                model.effect_chain.output_texture(),
+            )
+        })
+        .map_err(|e| util::ShaderError::new(filename, e.to_string()))?;
+
+    model.effect_chain.clear_history(encoder);
+    Ok(())
+}
+
+/// Reloads an imported ISF effect, whose passes and inputs may have changed. Inputs that are
+/// still declared keep their values.
+fn reload_isf_effect(
+    app: &App,
+    model: &mut Model,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    filename: &str,
+) -> Result<(), util::ShaderError> {
+    let (passes, mut parameters) = util::load_effect(app, device, filename)?;
+    if let (Some(parameters), Some(previous)) = (&mut parameters, &model.isf_parameters) {
+        parameters.inherit(previous);
+    }
+
+    let resources = effect_chain::EffectResources {
+        source: &model
+            .webcam_capture
+            .video_capture
+            .as_ref()
+            .unwrap()
+            .video_texture,
+        inputs: vec![],
+        landmarks: &model.landmarks,
+        storage_buffers: vec![],
+        uniform_buffers: uniform_buffers(&model.effect_library, &parameters),
+        sampler: &model.sampler,
+        uniforms: Some(model.uniforms.binding()),
+    };
+
+    model
+        .effect_chain
+        .replace_passes(device, filename, passes, &resources)
+        .and_then(|()| {
+            // the chain's output texture was replaced
+            model.compositor.set_layer_texture(
+                device,
+                "effects",
+                model.effect_chain.output_texture(),
+            )
+        })
+        .map_err(|e| util::ShaderError::new(filename, e.to_string()))?;
+
+    model.effect_chain.clear_history(encoder);
+    model.isf_parameters = parameters;
+    Ok(())
+}
+
+/// The uniform buffers to bind besides `Uniforms`: the parameters of the built-in effects and
+/// the inputs of an imported ISF effect.
+fn uniform_buffers<'a>(
+    effect_library: &'a effects::EffectLibrary,
+    isf_parameters: &'a Option<isf::IsfParameters>,
+) -> Vec<(&'a str, wgpu::BufferBinding<'a>)> {
+    let mut buffers = effect_library.bindings();
+    buffers.extend(
+        isf_parameters
+            .iter()
+            .map(|p| (isf::BLOCK_NAME, p.binding())),
+    );
+    buffers
+}
+
+fn resized(app: &App, model: &mut Model, _size: Vec2) {
+    let window = app.main_window();
+    let (width, height) = window.inner_size_pixels();
+    if width == 0 || height == 0 {
+        // minimized
+        return;
+    }
+
+    model.size = pt2(width as f32, height as f32);
+    model.uniforms.data.resolution = model.size.to_array();
+
+    if let Err(e) = model.compositor.resize(window.device(), width, height) {
+        println!("Error resizing compositor: {}", e);
+    }
+}
+
+fn key_pressed(_app: &App, model: &mut Model, key: Key) {
+    match key {
+        Key::L => {
+            if let Some(layer) = model.compositor.layer_mut("landmarks") {
+                layer.visible = !layer.visible;
+            }
+        }
+        Key::D => {
+            let stats = model.detector.stats();
+            println!(
+                "detector: {} frames processed, {} skipped",
+                stats.processed, stats.skipped
+            );
+            if let Some(latency) = stats.latency {
+                println!(
+                    "detector latency: {} frames, {:.1} ms",
+                    latency.frames,
+                    latency.duration.as_secs_f64() * 1000.0
+                );
+            }
+        }
+        Key::P => {
+            let max_prediction = if model.detector.max_prediction().is_zero() {
+                prediction::DEFAULT_MAX_HORIZON
+            } else {
+                std::time::Duration::ZERO
+            };
+            model.detector.set_max_prediction(max_prediction);
+            println!("landmark prediction: up to {:?}", max_prediction);
+        }
+        Key::S => {
+            let smoothing = match model.detector.smoothing() {
+                smoothing::Smoothing::OneEuro { .. } => smoothing::Smoothing::Ema { alpha: 0.5 },
+                smoothing::Smoothing::Ema { .. } => smoothing::Smoothing::Off,
+                smoothing::Smoothing::Off => smoothing::Smoothing::default(),
+            };
+            model.detector.set_smoothing(smoothing);
+            println!("landmark smoothing: {:?}", smoothing);
+        }
+        Key::V => {
+            model.delay_video = !model.delay_video;
+            model.frame_history.clear();
+            println!("delayed video: {}", model.delay_video);
+        }
+        _ => {}
     }
 }
 
@@ -99,17 +508,27 @@ fn view(app: &App, model: &Model, frame: Frame) {
     {
         let mut encoder = frame.command_encoder();
         model
-            .video_texture_reshaper
-            // .segmentor
-            // .texture_reshaper
-            .encode_render_pass(frame.texture_view(), &mut *encoder);
+            .compositor
+            .present(frame.texture_view(), &mut *encoder);
     }
 
     let draw = app.draw();
 
-    model
-        .detector
-        .draw_detections(&draw, &model.video_size, &model.size);
+    if !model.shader_errors.is_empty() {
+        let errors: Vec<_> = model
+            .shader_errors
+            .values()
+            .map(|e| e.to_string())
+            .collect();
+        let rect = app.window_rect().pad(20.0);
+        draw.text(&errors.join("\n\n"))
+            .wh(rect.wh())
+            .xy(rect.xy())
+            .left_justify()
+            .align_text_top()
+            .font_size(16)
+            .color(RED);
+    }
 
     draw.to_frame(app, &frame).unwrap();
 }
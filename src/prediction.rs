@@ -0,0 +1,148 @@
+use std::time::{Duration, Instant};
+
+use mediapipe::holistic::HolisticDetection;
+
+/// How far ahead of a detection to predict by default. Enough to cover a detector running at
+/// 15 Hz plus its latency, without overshooting badly when a movement stops.
+pub const DEFAULT_MAX_HORIZON: Duration = Duration::from_millis(150);
+
+/// Extrapolates landmarks from their velocity between detections, so overlays and shaders can
+/// move every frame while the detector runs at a lower rate.
+///
+/// The predictor keeps each detection's positions, so `predict` can be called any number of
+/// times between detections on the same (already predicted) detection.
+pub struct LandmarkPredictor {
+    max_horizon: Duration,
+    face: GroupPredictor,
+    pose: GroupPredictor,
+    left_hand: GroupPredictor,
+    right_hand: GroupPredictor,
+}
+
+impl Default for LandmarkPredictor {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_HORIZON)
+    }
+}
+
+impl LandmarkPredictor {
+    pub fn new(max_horizon: Duration) -> Self {
+        Self {
+            max_horizon,
+            face: GroupPredictor::default(),
+            pose: GroupPredictor::default(),
+            left_hand: GroupPredictor::default(),
+            right_hand: GroupPredictor::default(),
+        }
+    }
+
+    pub fn max_horizon(&self) -> Duration {
+        self.max_horizon
+    }
+
+    /// Sets how far past a detection positions are extrapolated. Zero turns prediction off.
+    pub fn set_max_horizon(&mut self, max_horizon: Duration) {
+        self.max_horizon = max_horizon;
+    }
+
+    /// Records a new detection, whose frame was captured at `time`.
+    pub fn observe(&mut self, detection: &HolisticDetection, time: Instant) {
+        match &detection.face {
+            Some(face) => self.face.observe(&face.data, time),
+            None => self.face.reset(),
+        }
+
+        match &detection.pose {
+            Some(pose) => self.pose.observe(&pose.data, time),
+            None => self.pose.reset(),
+        }
+
+        match &detection.left_hand {
+            Some(hand) => self.left_hand.observe(&hand.data, time),
+            None => self.left_hand.reset(),
+        }
+
+        match &detection.right_hand {
+            Some(hand) => self.right_hand.observe(&hand.data, time),
+            None => self.right_hand.reset(),
+        }
+    }
+
+    /// Moves the landmarks of the last observed detection to where they're expected to be at
+    /// `time`, at most `max_horizon` past the detection.
+    pub fn predict(&self, detection: &mut HolisticDetection, time: Instant) {
+        if let Some(face) = &mut detection.face {
+            self.face.predict(&mut face.data, time, self.max_horizon);
+        }
+
+        if let Some(pose) = &mut detection.pose {
+            self.pose.predict(&mut pose.data, time, self.max_horizon);
+        }
+
+        if let Some(hand) = &mut detection.left_hand {
+            self.left_hand
+                .predict(&mut hand.data, time, self.max_horizon);
+        }
+
+        if let Some(hand) = &mut detection.right_hand {
+            self.right_hand
+                .predict(&mut hand.data, time, self.max_horizon);
+        }
+    }
+}
+
+/// Detected positions and velocities for a group of landmarks.
+#[derive(Default)]
+struct GroupPredictor {
+    positions: Vec<[f32; 3]>,
+    velocities: Vec<[f32; 3]>,
+    time: Option<Instant>,
+}
+
+impl GroupPredictor {
+    fn reset(&mut self) {
+        self.positions.clear();
+        self.velocities.clear();
+        self.time = None;
+    }
+
+    fn observe(&mut self, landmarks: &[mediapipe::Landmark], time: Instant) {
+        let positions: Vec<_> = landmarks.iter().map(|l| [l.x, l.y, l.z]).collect();
+
+        let dt = self
+            .time
+            .map(|last| time.saturating_duration_since(last).as_secs_f32());
+
+        // velocities need a previous detection of the same landmarks
+        self.velocities = match dt {
+            Some(dt) if dt > 0.0 && self.positions.len() == positions.len() => positions
+                .iter()
+                .zip(self.positions.iter())
+                .map(|(new, old)| [0, 1, 2].map(|i| (new[i] - old[i]) / dt))
+                .collect(),
+            _ => vec![[0.0; 3]; positions.len()],
+        };
+
+        self.positions = positions;
+        self.time = Some(time);
+    }
+
+    fn predict(&self, landmarks: &mut [mediapipe::Landmark], time: Instant, max_horizon: Duration) {
+        let detected = match self.time {
+            Some(detected) if self.positions.len() == landmarks.len() => detected,
+            _ => return,
+        };
+
+        let horizon = time
+            .saturating_duration_since(detected)
+            .min(max_horizon)
+            .as_secs_f32();
+
+        let predicted = self.positions.iter().zip(self.velocities.iter());
+        for (landmark, (position, velocity)) in landmarks.iter_mut().zip(predicted) {
+            landmark.x = position[0] + velocity[0] * horizon;
+            landmark.y = position[1] + velocity[1] * horizon;
+            landmark.z = position[2] + velocity[2] * horizon;
+        }
+    }
+}
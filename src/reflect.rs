@@ -0,0 +1,282 @@
+use std::fmt;
+
+use nannou::prelude::*;
+
+/// What a shader expects to find at a binding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindingKind {
+    UniformBuffer,
+    StorageBuffer { read_only: bool },
+    Texture,
+    StorageTexture { access: wgpu::StorageTextureAccess },
+    Sampler { comparison: bool },
+}
+
+impl BindingKind {
+    pub fn description(&self) -> &'static str {
+        match self {
+            BindingKind::UniformBuffer => "uniform buffer",
+            BindingKind::StorageBuffer { .. } => "storage buffer",
+            BindingKind::Texture => "texture",
+            BindingKind::StorageTexture { .. } => "storage texture",
+            BindingKind::Sampler { .. } => "sampler",
+        }
+    }
+}
+
+/// A resource declared by a shader. Buffers are named by their block name (e.g. `Uniforms`),
+/// or struct name in WGSL, and everything else by its variable name (e.g. `tex_sampler`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderBinding {
+    pub name: String,
+    pub binding: u32,
+    pub kind: BindingKind,
+    /// The members of a uniform block, to check against the layout it's written with.
+    pub members: Vec<BlockMember>,
+}
+
+/// A member of a uniform block as the compiled shader lays it out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockMember {
+    pub name: Option<String>,
+    pub offset: u32,
+}
+
+#[derive(Debug)]
+pub enum ReflectError {
+    Parse(String),
+    /// Everything is bound in bind group 0.
+    UnsupportedGroup {
+        name: String,
+        group: u32,
+    },
+    UnsupportedType {
+        name: String,
+    },
+    UnnamedBinding {
+        binding: u32,
+    },
+    /// Pipelines are created with `main` as the entry point.
+    MissingEntryPoint,
+    /// `main` is for a different stage than the pass it was loaded for.
+    StageMismatch {
+        expected: naga::ShaderStage,
+        found: naga::ShaderStage,
+    },
+    Invalid(String),
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReflectError::Parse(message) => write!(f, "unable to reflect shader: {}", message),
+            ReflectError::UnsupportedGroup { name, group } => {
+                write!(f, "{} is in set {}, only set 0 is supported", name, group)
+            }
+            ReflectError::UnsupportedType { name } => {
+                write!(
+                    f,
+                    "{} has a type that can't be bound, only 2D textures are supported",
+                    name
+                )
+            }
+            ReflectError::UnnamedBinding { binding } => {
+                write!(f, "binding {} has no name to bind a resource by", binding)
+            }
+            ReflectError::MissingEntryPoint => write!(f, "the shader has no main entry point"),
+            ReflectError::StageMismatch { expected, found } => write!(
+                f,
+                "main is a {:?} entry point, expected a {:?} one",
+                found, expected
+            ),
+            ReflectError::Invalid(message) => write!(f, "invalid shader: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+/// A compiled effect shader along with the bindings it declares.
+pub struct EffectShader {
+    pub module: wgpu::ShaderModule,
+    pub bindings: Vec<ShaderBinding>,
+}
+
+impl EffectShader {
+    pub fn from_spirv(device: &wgpu::Device, spirv: &[u8]) -> Result<Self, ReflectError> {
+        let bindings = reflect_spirv(spirv)?;
+        let module = wgpu::shader_from_spirv_bytes(device, spirv);
+        Ok(Self { module, bindings })
+    }
+
+    /// Loads a WGSL shader, which wgpu compiles itself, so no shaderc is needed. The shader is
+    /// validated first, since wgpu panics on invalid modules, and its `main` must be a `stage`
+    /// entry point.
+    pub fn from_wgsl(
+        device: &wgpu::Device,
+        label: &str,
+        source: &str,
+        stage: naga::ShaderStage,
+    ) -> Result<Self, ReflectError> {
+        let parsed = parse_wgsl(source)?;
+        let found = main_stage(&parsed)?;
+        if found != stage {
+            return Err(ReflectError::StageMismatch {
+                expected: stage,
+                found,
+            });
+        }
+        validate_module(&parsed)?;
+        let bindings = reflect_module(&parsed)?;
+
+        let module = create_wgsl_module(device, label, source);
+        Ok(Self { module, bindings })
+    }
+}
+
+/// Lists the resource bindings of a WGSL module, sorted by binding index.
+/// Parse errors are formatted with the offending source line.
+pub fn reflect_wgsl(source: &str) -> Result<Vec<ShaderBinding>, ReflectError> {
+    let module = parse_wgsl(source)?;
+    main_stage(&module)?;
+    reflect_module(&module)
+}
+
+pub fn parse_wgsl(source: &str) -> Result<naga::Module, ReflectError> {
+    naga::front::wgsl::parse_str(source).map_err(|e| ReflectError::Parse(e.emit_to_string(source)))
+}
+
+pub fn parse_spirv(spirv: &[u8]) -> Result<naga::Module, ReflectError> {
+    naga::front::spv::parse_u8_slice(spirv, &Default::default())
+        .map_err(|e| ReflectError::Parse(format!("{:?}", e)))
+}
+
+/// The stage of the module's `main` entry point.
+pub fn main_stage(module: &naga::Module) -> Result<naga::ShaderStage, ReflectError> {
+    module
+        .entry_points
+        .iter()
+        .find(|e| e.name == "main")
+        .map(|e| e.stage)
+        .ok_or(ReflectError::MissingEntryPoint)
+}
+
+/// Runs the checks wgpu runs when it creates a shader module, without needing a device.
+pub fn validate_module(module: &naga::Module) -> Result<(), ReflectError> {
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(module)
+    .map(|_| ())
+    .map_err(|e| ReflectError::Invalid(e.to_string()))
+}
+
+pub fn create_wgsl_module(device: &wgpu::Device, label: &str, source: &str) -> wgpu::ShaderModule {
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+/// Lists the resource bindings of a SPIR-V module, sorted by binding index.
+pub fn reflect_spirv(spirv: &[u8]) -> Result<Vec<ShaderBinding>, ReflectError> {
+    reflect_module(&parse_spirv(spirv)?)
+}
+
+pub fn reflect_module(module: &naga::Module) -> Result<Vec<ShaderBinding>, ReflectError> {
+    let mut bindings = vec![];
+
+    for (_, variable) in module.global_variables.iter() {
+        let resource_binding = match &variable.binding {
+            Some(binding) => binding,
+            None => continue,
+        };
+
+        let ty = &module.types[variable.ty];
+
+        // buffers are known by their block (or in WGSL, struct) name, everything else by its
+        // variable name
+        let names = match variable.class {
+            naga::StorageClass::Uniform | naga::StorageClass::Storage { .. } => {
+                [ty.name.as_deref(), variable.name.as_deref()]
+            }
+            _ => [variable.name.as_deref(), ty.name.as_deref()],
+        };
+        let name = match names.iter().flatten().find(|name| !name.is_empty()) {
+            Some(name) => name.to_string(),
+            None => {
+                return Err(ReflectError::UnnamedBinding {
+                    binding: resource_binding.binding,
+                })
+            }
+        };
+
+        if resource_binding.group != 0 {
+            return Err(ReflectError::UnsupportedGroup {
+                name,
+                group: resource_binding.group,
+            });
+        }
+
+        let kind = match (variable.class, &ty.inner) {
+            (naga::StorageClass::Uniform, _) => BindingKind::UniformBuffer,
+            (naga::StorageClass::Storage { access }, _) => BindingKind::StorageBuffer {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            (
+                naga::StorageClass::Handle,
+                naga::TypeInner::Image {
+                    dim: naga::ImageDimension::D2,
+                    arrayed: false,
+                    class,
+                },
+            ) => match class {
+                naga::ImageClass::Sampled { multi: false, .. } => BindingKind::Texture,
+                naga::ImageClass::Storage { access, .. } => BindingKind::StorageTexture {
+                    access: storage_texture_access(*access),
+                },
+                _ => return Err(ReflectError::UnsupportedType { name }),
+            },
+            (naga::StorageClass::Handle, naga::TypeInner::Sampler { comparison }) => {
+                BindingKind::Sampler {
+                    comparison: *comparison,
+                }
+            }
+            _ => return Err(ReflectError::UnsupportedType { name }),
+        };
+
+        let members = match (kind, &ty.inner) {
+            (BindingKind::UniformBuffer, naga::TypeInner::Struct { members, .. }) => members
+                .iter()
+                .map(|m| BlockMember {
+                    name: m.name.clone(),
+                    offset: m.offset,
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        bindings.push(ShaderBinding {
+            name,
+            binding: resource_binding.binding,
+            kind,
+            members,
+        });
+    }
+
+    bindings.sort_by_key(|b| b.binding);
+
+    Ok(bindings)
+}
+
+fn storage_texture_access(access: naga::StorageAccess) -> wgpu::StorageTextureAccess {
+    let load = access.contains(naga::StorageAccess::LOAD);
+    let store = access.contains(naga::StorageAccess::STORE);
+
+    match (load, store) {
+        (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+        (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+        _ => wgpu::StorageTextureAccess::WriteOnly,
+    }
+}
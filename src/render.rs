@@ -1,5 +1,10 @@
+use std::fmt;
+
 use nannou::prelude::*;
 
+use crate::reflect::*;
+use crate::std140::UniformLayout;
+
 // The vertex type that we will use to represent a point on our triangle.
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -23,23 +28,316 @@ pub const VERTICES: [Vertex; 4] = [
     },
 ];
 
+/// Why a renderer or effect chain could not be built.
+/// Binding errors name the binding index the problem was found at.
 #[derive(Debug)]
 pub enum RendererError {
     MissingBufferSizes,
-    BufferCountAndBufferSizeCountMismatch,
+    BufferCountAndBufferSizeCountMismatch {
+        buffers: usize,
+        sizes: usize,
+    },
+    EmptyEffectChain,
+    TooManyHistoryFrames(usize),
+    BlendedComputePass,
+    TooManyLayers(usize),
+    /// Render targets can only have 1 or 4 samples.
+    UnsupportedSampleCount(u32),
+    /// A multisampled renderer resolves into its target, replacing it, so it can't blend over
+    /// or load the target's contents.
+    MultisampledBlend {
+        sample_count: u32,
+    },
+    ZeroSizedBuffer {
+        binding: u32,
+    },
+    /// Textures are bound as single sampled, so multisampled targets must be resolved first.
+    MultisampledInput {
+        binding: u32,
+        sample_count: u32,
+    },
+    /// Only 2D textures can be bound.
+    UnsupportedTextureDimension {
+        binding: u32,
+        dimension: wgpu::TextureDimension,
+    },
+    /// Textures are sampled with a filtering sampler, so they need a filterable float format.
+    UnsupportedSampleType {
+        binding: u32,
+        format: wgpu::TextureFormat,
+    },
+    MissingTextureUsage {
+        binding: u32,
+        usage: wgpu::TextureUsages,
+    },
+    /// The shader declares a binding no resource was given for.
+    MissingResource {
+        binding: u32,
+        name: String,
+    },
+    /// The resource given for a binding is not what the shader declares there.
+    ResourceKindMismatch {
+        binding: u32,
+        name: String,
+        expected: &'static str,
+    },
+    MisalignedUniformBuffer {
+        binding: u32,
+        offset: wgpu::BufferAddress,
+        alignment: wgpu::BufferAddress,
+    },
+    /// The shader declares a uniform block member that the block's layout doesn't have, or
+    /// has at a different offset, e.g. from a stale or hand written declaration.
+    UniformLayoutMismatch {
+        binding: u32,
+        name: String,
+        member: String,
+        offset: u32,
+        expected: Option<u32>,
+    },
+    /// An error building one pass of an effect chain.
+    Pass {
+        name: String,
+        source: Box<RendererError>,
+    },
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RendererError::MissingBufferSizes => write!(f, "buffers were given without sizes"),
+            RendererError::BufferCountAndBufferSizeCountMismatch { buffers, sizes } => write!(
+                f,
+                "{} buffers were given but {} buffer sizes",
+                buffers, sizes
+            ),
+            RendererError::EmptyEffectChain => write!(f, "an effect chain needs at least one pass"),
+            RendererError::TooManyHistoryFrames(frames) => write!(
+                f,
+                "{} history frames were requested but at most {} are supported",
+                frames,
+                crate::effect_chain::MAX_HISTORY_FRAMES
+            ),
+            RendererError::BlendedComputePass => {
+                write!(
+                    f,
+                    "compute passes can't be blended, they write their output directly"
+                )
+            }
+            RendererError::TooManyLayers(max) => {
+                write!(f, "a compositor can hold at most {} layers", max)
+            }
+            RendererError::UnsupportedSampleCount(count) => write!(
+                f,
+                "sample count {} is not supported, use one of {:?}",
+                count, SUPPORTED_SAMPLE_COUNTS
+            ),
+            RendererError::MultisampledBlend { sample_count } => write!(
+                f,
+                "a renderer with {} samples replaces its target, it can't blend or load it",
+                sample_count
+            ),
+            RendererError::ZeroSizedBuffer { binding } => {
+                write!(f, "binding {}: buffer has a size of 0", binding)
+            }
+            RendererError::MultisampledInput {
+                binding,
+                sample_count,
+            } => write!(
+                f,
+                "binding {}: texture has {} samples, resolve it before sampling",
+                binding, sample_count
+            ),
+            RendererError::UnsupportedTextureDimension { binding, dimension } => write!(
+                f,
+                "binding {}: texture is {:?}, only 2D textures can be bound",
+                binding, dimension
+            ),
+            RendererError::UnsupportedSampleType { binding, format } => write!(
+                f,
+                "binding {}: texture format {:?} can't be sampled with a filtering sampler",
+                binding, format
+            ),
+            RendererError::MissingTextureUsage { binding, usage } => write!(
+                f,
+                "binding {}: texture was not created with {:?}",
+                binding, usage
+            ),
+            RendererError::MissingResource { binding, name } => write!(
+                f,
+                "binding {}: the shader declares {} but no resource has that name",
+                binding, name
+            ),
+            RendererError::ResourceKindMismatch {
+                binding,
+                name,
+                expected,
+            } => write!(
+                f,
+                "binding {}: the shader declares {} as a {} but a different resource was given",
+                binding, name, expected
+            ),
+            RendererError::MisalignedUniformBuffer {
+                binding,
+                offset,
+                alignment,
+            } => write!(
+                f,
+                "binding {}: uniform buffer offset {} is not a multiple of {}",
+                binding, offset, alignment
+            ),
+            RendererError::UniformLayoutMismatch {
+                binding,
+                name,
+                member,
+                offset,
+                expected,
+            } => match expected {
+                Some(expected) => write!(
+                    f,
+                    "binding {}: {} declares {} at offset {}, but it's written at offset {}",
+                    binding, name, member, offset, expected
+                ),
+                None => write!(
+                    f,
+                    "binding {}: {} declares {} at offset {}, which isn't in its layout",
+                    binding, name, member, offset
+                ),
+            },
+            RendererError::Pass { name, source } => write!(f, "pass {}: {}", name, source),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::Pass { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// The sample counts every wgpu backend supports for render targets.
+pub const SUPPORTED_SAMPLE_COUNTS: [u32; 2] = [1, 4];
+
+/// How a pass's output is combined with what is already in its target.
+/// Each mode expects its source to have straight or premultiplied alpha, see
+/// `expects_premultiplied`. The compositor premultiplies layers for the modes that need it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    /// Overwrite the target, alpha included.
+    Replace,
+    /// Straight alpha: `src * a + dst * (1 - a)`.
+    Alpha,
+    /// Premultiplied alpha: `src + dst * (1 - a)`.
+    Premultiplied,
+    /// Straight alpha: `src * a + dst`.
+    Additive,
+    /// Premultiplied alpha: `src * dst + dst * (1 - a)`, so a transparent source leaves the
+    /// target unchanged.
+    Multiply,
+    /// Premultiplied alpha: `src + dst * (1 - src)`.
+    Screen,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Replace
+    }
+}
+
+impl BlendMode {
+    /// Whether the source colour must be premultiplied by its alpha for the mode to blend
+    /// correctly. Effect passes using these modes must output premultiplied alpha.
+    pub fn expects_premultiplied(&self) -> bool {
+        matches!(
+            self,
+            BlendMode::Premultiplied | BlendMode::Multiply | BlendMode::Screen
+        )
+    }
+
+    pub fn blend_state(&self) -> wgpu::BlendState {
+        let over = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+            operation: wgpu::BlendOperation::Add,
+        };
+
+        let color = match self {
+            BlendMode::Replace => wgpu::BlendComponent::REPLACE,
+            BlendMode::Alpha => wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            BlendMode::Premultiplied => over,
+            BlendMode::Additive => wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::SrcAlpha,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            // src * dst + dst * (1 - a), with src premultiplied
+            BlendMode::Multiply => wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Dst,
+                dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                operation: wgpu::BlendOperation::Add,
+            },
+            // src + dst - src * dst, with src premultiplied
+            BlendMode::Screen => wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::OneMinusSrc,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+
+        let alpha = match self {
+            BlendMode::Replace => wgpu::BlendComponent::REPLACE,
+            BlendMode::Additive => wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            _ => over,
+        };
+
+        wgpu::BlendState { color, alpha }
+    }
+}
+
+/// Where a renderer draws.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenderOutput {
+    /// Into an output texture of its own, with `render`.
+    Owned,
+    /// Only into targets given to `render_to`, e.g. for blended passes and compositor layers,
+    /// so no output texture is allocated.
+    External,
 }
 
 pub struct CustomRenderer {
     bind_group: wgpu::BindGroup,
+    blend_mode: BlendMode,
+    pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
-    pub output_texture: wgpu::Texture,
-    pub texture_reshaper: wgpu::TextureReshaper,
+    sample_count: u32,
+    /// The multisampled render target, resolved into `output_texture` at the end of every pass.
+    msaa_texture: Option<wgpu::Texture>,
+    /// Always single sampled, so later passes can bind it as a regular texture.
+    /// `None` for renderers created with `RenderOutput::External`.
+    pub output_texture: Option<wgpu::Texture>,
+    pub texture_reshaper: Option<wgpu::TextureReshaper>,
     pub vertex_buffer: wgpu::Buffer,
 }
 
-/// A render pipeline generator for a fragment shader with optional textures, sampler, and uniform buffer
+/// A render pipeline generator for a fragment shader with optional textures, sampler, and uniform buffer.
+///
+/// With a `sample_count` above 1 the pass renders into a multisampled texture that is resolved
+/// into `output_texture` before anything samples it. Bound textures must be single sampled,
+/// e.g. the resolved outputs of other renderers. `device_sample_count` is the sample count of the
+/// frame the texture reshaper draws `output_texture` into.
 impl CustomRenderer {
-    pub fn new<T>(
+    pub fn new(
         device: &wgpu::Device,
         vs_mod: &wgpu::ShaderModule,
         fs_mod: &wgpu::ShaderModule,
@@ -47,44 +345,45 @@ impl CustomRenderer {
         buffer_sizes: Option<&Vec<&wgpu::BufferAddress>>,
         uniform_textures: Option<&Vec<&wgpu::Texture>>,
         sampler: Option<&wgpu::Sampler>,
-        uniform_buffer: Option<&wgpu::Buffer>,
+        uniform_buffer: Option<wgpu::BufferBinding>,
+        blend_mode: BlendMode,
+        output: RenderOutput,
         width: u32,
         height: u32,
-        texture_sample_count: u32,
+        sample_count: u32,
         device_sample_count: u32,
-    ) -> Result<Self, RendererError>
-    where
-        T: Copy,
-    {
+    ) -> Result<Self, RendererError> {
+        check_sample_count(sample_count, blend_mode)?;
+
+        let buffer_count = check_buffers(buffers, buffer_sizes)?;
+        let texture_count = match uniform_textures {
+            Some(textures) => check_textures(textures, buffer_count, sampler.is_some())?,
+            None => 0,
+        };
+        if let Some(binding) = uniform_buffer.as_ref() {
+            let sampler_count = sampler.is_some() as u32;
+            let index = buffer_count + texture_count + sampler_count;
+            check_uniform_buffer(device, binding, index)?;
+        }
+
         println!("creating bind group");
 
         let mut bind_group_layout_builder = wgpu::BindGroupLayoutBuilder::new();
         let mut bind_group_builder = wgpu::BindGroupBuilder::new();
 
-        if let Some(b) = buffers {
-            if let Some(s) = buffer_sizes {
-                if b.len() != s.len() {
-                    return Err(RendererError::BufferCountAndBufferSizeCountMismatch);
-                }
-
-                let storage_dynamic = false;
-                let storage_readonly = false;
+        if let (Some(b), Some(s)) = (buffers, buffer_sizes) {
+            let storage_dynamic = false;
+            let storage_readonly = false;
 
-                for (i, buffer) in b.iter().enumerate() {
-                    let buffer_size = *s[i];
-
-                    bind_group_layout_builder = bind_group_layout_builder.storage_buffer(
-                        wgpu::ShaderStages::FRAGMENT,
-                        storage_dynamic,
-                        storage_readonly,
-                    );
+            for (buffer, size) in b.iter().zip(s.iter()) {
+                bind_group_layout_builder = bind_group_layout_builder.storage_buffer(
+                    wgpu::ShaderStages::FRAGMENT,
+                    storage_dynamic,
+                    storage_readonly,
+                );
 
-                    let buffer_size_bytes = std::num::NonZeroU64::new(buffer_size).unwrap();
-                    bind_group_builder =
-                        bind_group_builder.buffer_bytes(buffer, 0, Some(buffer_size_bytes));
-                }
-            } else {
-                return Err(RendererError::MissingBufferSizes);
+                bind_group_builder =
+                    bind_group_builder.buffer_bytes(buffer, 0, wgpu::BufferSize::new(**size));
             }
         }
 
@@ -102,7 +401,7 @@ impl CustomRenderer {
             for t in textures.iter() {
                 bind_group_layout_builder = bind_group_layout_builder.texture(
                     wgpu::ShaderStages::FRAGMENT,
-                    texture_sample_count > 1,
+                    false,
                     wgpu::TextureViewDimension::D2,
                     t.sample_type(),
                 )
@@ -122,16 +421,84 @@ impl CustomRenderer {
             bind_group_builder = bind_group_builder.sampler(s);
         }
 
-        if let Some(ref buffer) = uniform_buffer {
+        if let Some(binding) = uniform_buffer {
             bind_group_layout_builder =
                 bind_group_layout_builder.uniform_buffer(wgpu::ShaderStages::FRAGMENT, false);
 
-            bind_group_builder = bind_group_builder.buffer::<T>(buffer, 0..1);
+            // the size is determined by the std140 layout of the block
+            bind_group_builder =
+                bind_group_builder.buffer_bytes(binding.buffer, binding.offset, binding.size);
         }
 
         let bind_group_layout = bind_group_layout_builder.build(device);
         let bind_group = bind_group_builder.build(device, &bind_group_layout);
 
+        Ok(Self::from_bind_group(
+            device,
+            vs_mod,
+            fs_mod,
+            bind_group_layout,
+            bind_group,
+            blend_mode,
+            output,
+            width,
+            height,
+            sample_count,
+            device_sample_count,
+        ))
+    }
+
+    /// Creates a renderer for an effect shader, binding each resource it declares by name.
+    pub fn with_bindings(
+        device: &wgpu::Device,
+        vs_mod: &wgpu::ShaderModule,
+        fs: &EffectShader,
+        resources: &NamedResources,
+        blend_mode: BlendMode,
+        output: RenderOutput,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        device_sample_count: u32,
+    ) -> Result<Self, RendererError> {
+        check_sample_count(sample_count, blend_mode)?;
+
+        println!("creating bind group");
+        let (bind_group_layout, bind_group) = create_bind_group(
+            device,
+            &fs.bindings,
+            resources,
+            wgpu::ShaderStages::FRAGMENT,
+        )?;
+
+        Ok(Self::from_bind_group(
+            device,
+            vs_mod,
+            &fs.module,
+            bind_group_layout,
+            bind_group,
+            blend_mode,
+            output,
+            width,
+            height,
+            sample_count,
+            device_sample_count,
+        ))
+    }
+
+    fn from_bind_group(
+        device: &wgpu::Device,
+        vs_mod: &wgpu::ShaderModule,
+        fs_mod: &wgpu::ShaderModule,
+        bind_group_layout: wgpu::BindGroupLayout,
+        bind_group: wgpu::BindGroup,
+        blend_mode: BlendMode,
+        output: RenderOutput,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        device_sample_count: u32,
+    ) -> Self {
         println!("creating pipeline layout");
         let pipeline_layout = create_pipeline_layout(device, &bind_group_layout);
 
@@ -141,18 +508,24 @@ impl CustomRenderer {
             &pipeline_layout,
             &vs_mod,
             &fs_mod,
-            texture_sample_count,
+            sample_count,
+            blend_mode,
         );
 
         println!("creating texture and reshaper");
 
-        let output_texture = create_app_texture(&device, width, height, texture_sample_count);
-        let texture_reshaper = create_texture_reshaper(
-            &device,
-            &output_texture,
-            texture_sample_count,
-            device_sample_count,
-        );
+        let msaa_texture = match sample_count {
+            1 => None,
+            _ => Some(create_app_texture(&device, width, height, sample_count)),
+        };
+        let (output_texture, texture_reshaper) = match output {
+            RenderOutput::Owned => {
+                let texture = create_app_texture(&device, width, height, 1);
+                let reshaper = create_texture_reshaper(&device, &texture, 1, device_sample_count);
+                (Some(texture), Some(reshaper))
+            }
+            RenderOutput::External => (None, None),
+        };
 
         println!("creating vertex buffer");
 
@@ -163,20 +536,108 @@ impl CustomRenderer {
             usage: wgpu::BufferUsages::VERTEX,
         });
 
-        Ok(Self {
+        Self {
             bind_group,
+            blend_mode,
+            pipeline_layout,
             render_pipeline,
+            sample_count,
+            msaa_texture,
             output_texture,
             texture_reshaper,
             vertex_buffer,
-        })
+        }
+    }
+
+    /// Rebuilds the render pipeline with new shader modules, keeping the existing bindings.
+    /// The shaders must declare the same bindings as the ones the renderer was created with.
+    pub fn rebuild_pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        vs_mod: &wgpu::ShaderModule,
+        fs_mod: &wgpu::ShaderModule,
+    ) {
+        self.render_pipeline = create_render_pipeline(
+            device,
+            &self.pipeline_layout,
+            vs_mod,
+            fs_mod,
+            self.sample_count,
+            self.blend_mode,
+        );
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Changes the blend mode, rebuilding the render pipeline.
+    /// Multisampled renderers can only use `BlendMode::Replace`.
+    pub fn set_blend_mode(
+        &mut self,
+        device: &wgpu::Device,
+        vs_mod: &wgpu::ShaderModule,
+        fs_mod: &wgpu::ShaderModule,
+        blend_mode: BlendMode,
+    ) -> Result<(), RendererError> {
+        check_sample_count(self.sample_count, blend_mode)?;
+        self.blend_mode = blend_mode;
+        self.rebuild_pipeline(device, vs_mod, fs_mod);
+        Ok(())
     }
 
+    /// Renders into the renderer's own output texture, clearing it first.
+    /// Does nothing for renderers without one.
     pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
-        let texture_view = self.output_texture.view().build();
-        let mut render_pass = wgpu::RenderPassBuilder::new()
-            .color_attachment(&texture_view, |color| color)
-            .begin(encoder);
+        let texture_view = match self.output_texture.as_ref() {
+            Some(texture) => texture.view().build(),
+            None => return,
+        };
+        self.encode(
+            encoder,
+            &texture_view,
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+        );
+    }
+
+    /// Renders into another single sampled target with the renderer's format.
+    /// Use `wgpu::LoadOp::Load` to blend over the target's existing contents, so several
+    /// renderers can be composited into the same texture.
+    ///
+    /// A multisampled renderer draws into its own multisampled texture and resolves it into the
+    /// target, replacing the target's contents, so loading them is an error.
+    pub fn render_to(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture_view: &wgpu::TextureView,
+        load_op: wgpu::LoadOp<wgpu::Color>,
+    ) -> Result<(), RendererError> {
+        if self.sample_count > 1 && matches!(load_op, wgpu::LoadOp::Load) {
+            return Err(RendererError::MultisampledBlend {
+                sample_count: self.sample_count,
+            });
+        }
+
+        self.encode(encoder, texture_view, load_op);
+        Ok(())
+    }
+
+    fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture_view: &wgpu::TextureView,
+        load_op: wgpu::LoadOp<wgpu::Color>,
+    ) {
+        let msaa_view = self.msaa_texture.as_ref().map(|t| t.view().build());
+        let builder = match msaa_view.as_ref() {
+            Some(msaa_view) => wgpu::RenderPassBuilder::new()
+                .color_attachment(msaa_view, |color| {
+                    color.resolve_target(Some(texture_view)).load_op(load_op)
+                }),
+            None => wgpu::RenderPassBuilder::new()
+                .color_attachment(texture_view, |color| color.load_op(load_op)),
+        };
+        let mut render_pass = builder.begin(encoder);
         render_pass.set_bind_group(0, &self.bind_group, &[]);
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
@@ -186,6 +647,18 @@ impl CustomRenderer {
     }
 }
 
+fn check_sample_count(sample_count: u32, blend_mode: BlendMode) -> Result<(), RendererError> {
+    if !SUPPORTED_SAMPLE_COUNTS.contains(&sample_count) {
+        return Err(RendererError::UnsupportedSampleCount(sample_count));
+    }
+
+    if sample_count > 1 && blend_mode != BlendMode::Replace {
+        return Err(RendererError::MultisampledBlend { sample_count });
+    }
+
+    Ok(())
+}
+
 pub fn create_app_texture(
     device: &wgpu::Device,
     width: u32,
@@ -224,7 +697,7 @@ pub fn create_texture_reshaper(
     )
 }
 
-fn create_pipeline_layout(
+pub fn create_pipeline_layout(
     device: &wgpu::Device,
     bind_group_layout: &wgpu::BindGroupLayout,
 ) -> wgpu::PipelineLayout {
@@ -242,16 +715,367 @@ pub fn create_render_pipeline(
     vs_mod: &wgpu::ShaderModule,
     fs_mod: &wgpu::ShaderModule,
     sample_count: u32,
+    blend_mode: BlendMode,
 ) -> wgpu::RenderPipeline {
+    let blend_state = blend_mode.blend_state();
     wgpu::RenderPipelineBuilder::from_layout(layout, vs_mod)
         .fragment_shader(fs_mod)
         .color_format(Frame::TEXTURE_FORMAT)
+        .color_blend(blend_state.color)
+        .alpha_blend(blend_state.alpha)
         .add_vertex_buffer::<Vertex>(&wgpu::vertex_attr_array![0 => Float32x2])
         .sample_count(sample_count)
         .primitive_topology(wgpu::PrimitiveTopology::TriangleStrip)
         .build(device)
 }
 
+/// A resource that can be bound to whichever binding a shader declares with the same name.
+#[derive(Clone)]
+pub enum NamedResource<'a> {
+    /// A uniform or storage buffer, depending on how the shader declares it.
+    Buffer(wgpu::BufferBinding<'a>),
+    /// A buffer given a size of 0, which can't be bound. `BufferSize` can't hold 0, and a
+    /// binding without a size would bind the whole buffer, so binding it is an error instead.
+    EmptyBuffer,
+    /// A sampled or storage texture, depending on how the shader declares it.
+    Texture(&'a wgpu::Texture),
+    Sampler(&'a wgpu::Sampler),
+}
+
+/// The resources a shader can bind, by name. Resources the shader doesn't declare are ignored.
+#[derive(Clone, Default)]
+pub struct NamedResources<'a> {
+    resources: Vec<(String, NamedResource<'a>)>,
+    layouts: Vec<UniformLayout>,
+}
+
+impl<'a> NamedResources<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn buffer(self, name: &str, binding: wgpu::BufferBinding<'a>) -> Self {
+        self.with(name, NamedResource::Buffer(binding))
+    }
+
+    /// Adds the first `size` bytes of a buffer. A size of 0 fails to bind.
+    pub fn sized_buffer(
+        self,
+        name: &str,
+        buffer: &'a wgpu::Buffer,
+        size: wgpu::BufferAddress,
+    ) -> Self {
+        match wgpu::BufferSize::new(size) {
+            Some(size) => self.buffer(
+                name,
+                wgpu::BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: Some(size),
+                },
+            ),
+            None => self.with(name, NamedResource::EmptyBuffer),
+        }
+    }
+
+    pub fn texture(self, name: &str, texture: &'a wgpu::Texture) -> Self {
+        self.with(name, NamedResource::Texture(texture))
+    }
+
+    pub fn sampler(self, name: &str, sampler: &'a wgpu::Sampler) -> Self {
+        self.with(name, NamedResource::Sampler(sampler))
+    }
+
+    /// Adds a uniform buffer written with `layout`, named after its block. Shaders that declare
+    /// the block with different members or offsets fail to bind.
+    pub fn uniform_block(self, binding: wgpu::BufferBinding<'a>, layout: &UniformLayout) -> Self {
+        let mut resources = self.buffer(&layout.name, binding);
+        resources.layouts.push(layout.clone());
+        resources
+    }
+
+    /// Adds a resource, replacing any resource with the same name.
+    pub fn with(mut self, name: &str, resource: NamedResource<'a>) -> Self {
+        self.resources.retain(|(n, _)| n != name);
+        self.layouts.retain(|layout| layout.name != name);
+        self.resources.push((name.to_string(), resource));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NamedResource<'a>> {
+        self.resources
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, resource)| resource)
+    }
+
+    /// The layout a uniform block added with `uniform_block` is written with.
+    pub fn layout(&self, name: &str) -> Option<&UniformLayout> {
+        self.layouts.iter().find(|layout| layout.name == name)
+    }
+}
+
+/// Builds a bind group layout matching the bindings a shader declares, and a bind group
+/// binding each of them to the resource of the same name.
+pub fn create_bind_group(
+    device: &wgpu::Device,
+    bindings: &[ShaderBinding],
+    resources: &NamedResources,
+    visibility: wgpu::ShaderStages,
+) -> Result<(wgpu::BindGroupLayout, wgpu::BindGroup), RendererError> {
+    let filtering = bindings
+        .iter()
+        .any(|b| matches!(b.kind, BindingKind::Sampler { .. }));
+
+    let mut layout_entries = vec![];
+    let mut texture_views = vec![];
+
+    for binding in bindings {
+        let resource =
+            resources
+                .get(&binding.name)
+                .ok_or_else(|| RendererError::MissingResource {
+                    binding: binding.binding,
+                    name: binding.name.clone(),
+                })?;
+
+        let ty = match (binding.kind, resource) {
+            (
+                BindingKind::UniformBuffer | BindingKind::StorageBuffer { .. },
+                NamedResource::EmptyBuffer,
+            ) => {
+                return Err(RendererError::ZeroSizedBuffer {
+                    binding: binding.binding,
+                })
+            }
+            (BindingKind::UniformBuffer, NamedResource::Buffer(buffer)) => {
+                check_uniform_buffer(device, buffer, binding.binding)?;
+                if let Some(layout) = resources.layout(&binding.name) {
+                    check_uniform_layout(binding, layout)?;
+                }
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                }
+            }
+            (BindingKind::StorageBuffer { read_only }, NamedResource::Buffer(_)) => {
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                }
+            }
+            (BindingKind::Texture, NamedResource::Texture(texture)) => {
+                check_textures(&[texture], binding.binding, filtering)?;
+                texture_views.push(texture.view().build());
+                wgpu::BindingType::Texture {
+                    sample_type: texture.sample_type(),
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                }
+            }
+            (BindingKind::StorageTexture { access }, NamedResource::Texture(texture)) => {
+                if !texture
+                    .usage()
+                    .contains(wgpu::TextureUsages::STORAGE_BINDING)
+                {
+                    return Err(RendererError::MissingTextureUsage {
+                        binding: binding.binding,
+                        usage: wgpu::TextureUsages::STORAGE_BINDING,
+                    });
+                }
+                texture_views.push(texture.view().build());
+                wgpu::BindingType::StorageTexture {
+                    access,
+                    format: texture.format(),
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                }
+            }
+            (BindingKind::Sampler { comparison }, NamedResource::Sampler(_)) => {
+                wgpu::BindingType::Sampler {
+                    filtering: true,
+                    comparison,
+                }
+            }
+            (kind, _) => {
+                return Err(RendererError::ResourceKindMismatch {
+                    binding: binding.binding,
+                    name: binding.name.clone(),
+                    expected: kind.description(),
+                })
+            }
+        };
+
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: binding.binding,
+            visibility,
+            ty,
+            count: None,
+        });
+    }
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("named-bind-group-layout"),
+        entries: &layout_entries,
+    });
+
+    // texture views were created in binding order
+    let mut views = texture_views.iter();
+    let entries: Vec<wgpu::BindGroupEntry> = bindings
+        .iter()
+        .map(|binding| {
+            let resource = match resources.get(&binding.name).unwrap() {
+                NamedResource::Buffer(buffer) => wgpu::BindingResource::Buffer(buffer.clone()),
+                NamedResource::EmptyBuffer => unreachable!("empty buffers fail to bind"),
+                NamedResource::Texture(_) => {
+                    wgpu::BindingResource::TextureView(views.next().unwrap())
+                }
+                NamedResource::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+            };
+
+            wgpu::BindGroupEntry {
+                binding: binding.binding,
+                resource,
+            }
+        })
+        .collect();
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("named-bind-group"),
+        layout: &bind_group_layout,
+        entries: &entries,
+    });
+
+    Ok((bind_group_layout, bind_group))
+}
+
+/// Checks that buffers and their sizes match up and that no buffer is empty.
+/// Returns the number of buffers, which are bound from binding 0.
+pub fn check_buffers(
+    buffers: Option<&Vec<&wgpu::Buffer>>,
+    buffer_sizes: Option<&Vec<&wgpu::BufferAddress>>,
+) -> Result<u32, RendererError> {
+    let (buffers, sizes) = match (buffers, buffer_sizes) {
+        (Some(buffers), Some(sizes)) => (buffers, sizes),
+        (Some(_), None) => return Err(RendererError::MissingBufferSizes),
+        (None, _) => return Ok(0),
+    };
+
+    if buffers.len() != sizes.len() {
+        return Err(RendererError::BufferCountAndBufferSizeCountMismatch {
+            buffers: buffers.len(),
+            sizes: sizes.len(),
+        });
+    }
+
+    if let Some(i) = sizes.iter().position(|size| **size == 0) {
+        return Err(RendererError::ZeroSizedBuffer { binding: i as u32 });
+    }
+
+    Ok(buffers.len() as u32)
+}
+
+/// Checks that every texture bound from `first_binding` on can be sampled as a regular 2D texture,
+/// with a filtering sampler if `filtering` is set. Returns the number of textures.
+pub fn check_textures(
+    textures: &[&wgpu::Texture],
+    first_binding: u32,
+    filtering: bool,
+) -> Result<u32, RendererError> {
+    for (i, texture) in textures.iter().enumerate() {
+        let binding = first_binding + i as u32;
+        let descriptor = texture.descriptor();
+
+        if descriptor.sample_count > 1 {
+            return Err(RendererError::MultisampledInput {
+                binding,
+                sample_count: descriptor.sample_count,
+            });
+        }
+
+        if descriptor.dimension != wgpu::TextureDimension::D2 {
+            return Err(RendererError::UnsupportedTextureDimension {
+                binding,
+                dimension: descriptor.dimension,
+            });
+        }
+
+        if !descriptor
+            .usage
+            .contains(wgpu::TextureUsages::TEXTURE_BINDING)
+        {
+            return Err(RendererError::MissingTextureUsage {
+                binding,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            });
+        }
+
+        let sample_type = descriptor.format.describe().sample_type;
+        let supported = match sample_type {
+            wgpu::TextureSampleType::Float { filterable } => filterable || !filtering,
+            _ => false,
+        };
+        if !supported {
+            return Err(RendererError::UnsupportedSampleType {
+                binding,
+                format: descriptor.format,
+            });
+        }
+    }
+
+    Ok(textures.len() as u32)
+}
+
+/// Checks that a shader declares a uniform block with the offsets of the layout it's written
+/// with. Members are matched by name, or by position if the shader has no names for them.
+/// The shader may leave out members, but not add any.
+pub fn check_uniform_layout(
+    binding: &ShaderBinding,
+    layout: &UniformLayout,
+) -> Result<(), RendererError> {
+    for (i, member) in binding.members.iter().enumerate() {
+        let expected = match &member.name {
+            Some(name) => layout.fields.iter().find(|f| f.name == *name),
+            None => layout.fields.get(i),
+        }
+        .map(|f| f.offset as u32);
+
+        if expected != Some(member.offset) {
+            return Err(RendererError::UniformLayoutMismatch {
+                binding: binding.binding,
+                name: binding.name.clone(),
+                member: member
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("member {}", i)),
+                offset: member.offset,
+                expected,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a uniform buffer bound at `binding` starts at an offset the device can bind.
+pub fn check_uniform_buffer(
+    device: &wgpu::Device,
+    buffer: &wgpu::BufferBinding,
+    binding: u32,
+) -> Result<(), RendererError> {
+    let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+    if buffer.offset % alignment != 0 {
+        return Err(RendererError::MisalignedUniformBuffer {
+            binding,
+            offset: buffer.offset,
+            alignment,
+        });
+    }
+
+    Ok(())
+}
+
 /// See the `nannou::wgpu::bytes` documentation for why this is necessary.
 fn vertices_as_bytes(data: &[Vertex]) -> &[u8] {
     unsafe { wgpu::bytes::from_slice(data) }
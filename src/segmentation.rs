@@ -1,74 +1,52 @@
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread;
-
 use mediapipe;
 use nannou::prelude::*;
 use opencv::prelude::*;
 
+use crate::detector::*;
 use crate::render::*;
 use crate::texture;
+use crate::video_capture::FrameTag;
+
+/// Runs the segmentation model and turns its mask back into an unmirrored BGR image,
+/// like the camera frames.
+struct SegmentationDetector {
+    segmentor: mediapipe::segmentation::Segmentor,
+    flip_frame: Mat,
+}
+
+impl Detector for SegmentationDetector {
+    type Output = Mat;
+
+    fn process(&mut self, frame: &Mat) -> Mat {
+        let mut result = self.segmentor.process(frame);
+
+        opencv::core::flip(&result, &mut self.flip_frame, 1).unwrap();
+
+        opencv::imgproc::cvt_color(
+            &self.flip_frame,
+            &mut result,
+            opencv::imgproc::COLOR_RGB2BGR,
+            0,
+        )
+        .unwrap();
+
+        result
+    }
+}
 
 pub struct Segmentor {
     pub output_texture: wgpu::Texture,
     pub texture_reshaper: wgpu::TextureReshaper,
     video_width: u32,
     video_height: u32,
-    request_sender: Sender<Mat>,
-    response_receiver: Receiver<Mat>,
-    worker_thread: thread::JoinHandle<()>,
+    worker: DetectorWorker<SegmentationDetector>,
 }
 
 impl Segmentor {
     pub fn new(device: &wgpu::Device, video_size: Vec2, sample_count: u32) -> Self {
-        let (request_sender, request_receiver) = channel::<Mat>();
-        let (response_sender, response_receiver) = channel::<Mat>();
-
-        let worker_thread = thread::spawn(move || {
-            let mut detector = mediapipe::segmentation::Segmentor::default();
-
-            let mut rgb_frame = unsafe {
-                opencv::core::Mat::new_rows_cols(
-                    video_size.x.round() as i32,
-                    video_size.y.round() as i32,
-                    opencv::core::CV_8UC3,
-                )
-                .unwrap()
-            };
-
-            let mut flip_frame = unsafe {
-                opencv::core::Mat::new_rows_cols(
-                    video_size.x.round() as i32,
-                    video_size.y.round() as i32,
-                    opencv::core::CV_8UC3,
-                )
-                .unwrap()
-            };
-
-            for frame in request_receiver.iter() {
-                opencv::imgproc::cvt_color(
-                    &frame,
-                    &mut rgb_frame,
-                    opencv::imgproc::COLOR_BGR2RGB,
-                    0,
-                )
-                .unwrap();
-
-                opencv::core::flip(&rgb_frame, &mut flip_frame, 1).unwrap(); // horizontal
-
-                let mut result = detector.process(&flip_frame);
-
-                opencv::core::flip(&result, &mut flip_frame, 1).unwrap();
-
-                opencv::imgproc::cvt_color(
-                    &flip_frame,
-                    &mut result,
-                    opencv::imgproc::COLOR_RGB2BGR,
-                    0,
-                )
-                .unwrap();
-
-                response_sender.send(result).unwrap();
-            }
+        let worker = DetectorWorker::new("segmentation", || SegmentationDetector {
+            segmentor: mediapipe::segmentation::Segmentor::default(),
+            flip_frame: Mat::default(),
         });
 
         let video_width = video_size.x as u32;
@@ -80,11 +58,9 @@ impl Segmentor {
         Self {
             output_texture,
             texture_reshaper,
-            request_sender,
-            response_receiver,
-            worker_thread,
             video_width,
             video_height,
+            worker,
         }
     }
 
@@ -92,22 +68,24 @@ impl Segmentor {
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
+        tag: FrameTag,
         frame: &Mat,
     ) {
-        self.request_sender.send(frame.clone()).unwrap();
+        self.worker.send(tag, frame);
+
+        if let Some(result) = self.worker.try_recv() {
+            texture::upload_mat_rgb(
+                device,
+                encoder,
+                &result.output,
+                &self.output_texture,
+                self.video_width,
+                self.video_height,
+            );
+        }
+    }
 
-        match self.response_receiver.try_recv() {
-            Ok(result) => {
-                texture::upload_mat_rgb(
-                    device,
-                    encoder,
-                    &result,
-                    &self.output_texture,
-                    self.video_width,
-                    self.video_height,
-                );
-            }
-            Err(_) => return,
-        };
+    pub fn stats(&self) -> DetectorStats {
+        self.worker.stats()
     }
 }
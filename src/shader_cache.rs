@@ -0,0 +1,157 @@
+use std::fs;
+use std::path::PathBuf;
+
+/// Bump to invalidate every cache entry, e.g. when the entry format changes.
+const CACHE_VERSION: u32 = 2;
+
+/// The locked shaderc and glslang versions, set by build.rs, so upgrading the compiler
+/// invalidates SPIR-V it compiled.
+const COMPILER_VERSION: &str = env!("SHADERC_VERSION");
+
+/// An include file a cached shader was compiled with, recorded the way shaderc requested it
+/// so it can be resolved again to check whether it changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub requested: String,
+    pub relative: bool,
+    pub requesting: String,
+    pub hash: u64,
+}
+
+impl Dependency {
+    pub fn new(
+        requested: &str,
+        include_type: shaderc::IncludeType,
+        requesting: &str,
+        content: &str,
+    ) -> Self {
+        Self {
+            requested: requested.to_string(),
+            relative: matches!(include_type, shaderc::IncludeType::Relative),
+            requesting: requesting.to_string(),
+            hash: hash_bytes(content.as_bytes()),
+        }
+    }
+
+    pub fn include_type(&self) -> shaderc::IncludeType {
+        if self.relative {
+            shaderc::IncludeType::Relative
+        } else {
+            shaderc::IncludeType::Standard
+        }
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{:016x}",
+            self.relative as u8, self.requested, self.requesting, self.hash
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let relative = fields.next()? == "1";
+        let requested = fields.next()?.to_string();
+        let requesting = fields.next()?.to_string();
+        let hash = u64::from_str_radix(fields.next()?, 16).ok()?;
+
+        Some(Self {
+            requested,
+            relative,
+            requesting,
+            hash,
+        })
+    }
+}
+
+/// Compiled SPIR-V stored on disk so unchanged shaders don't have to be recompiled on launch.
+///
+/// Entries are keyed by a hash of the shader's file name, source, kind, compile options, macro
+/// definitions, the compiler version and the SPIR-V version it emits. Each entry records the includes it was compiled with and is only
+/// used if all of them still resolve to the same contents.
+/// The cache is best effort: I/O errors are reported and treated as misses.
+pub struct ShaderCache {
+    dir: PathBuf,
+}
+
+impl ShaderCache {
+    pub fn new(dir: PathBuf) -> Self {
+        if let Err(e) = fs::create_dir_all(&dir) {
+            println!("unable to create shader cache {}: {}", dir.display(), e);
+        }
+
+        Self { dir }
+    }
+
+    pub fn key(
+        filename: &str,
+        source: &str,
+        kind: shaderc::ShaderKind,
+        options: &str,
+        defines: &[(&str, Option<&str>)],
+    ) -> u64 {
+        let (spirv_major, spirv_minor) = shaderc::get_spirv_version();
+
+        let mut keyed = format!(
+            "{}\n{}\n{:?}\n{}\n{}\n{}.{}\n",
+            CACHE_VERSION, filename, kind, options, COMPILER_VERSION, spirv_major, spirv_minor
+        );
+        for (name, value) in defines.iter() {
+            keyed.push_str(&format!("{}={}\n", name, value.unwrap_or("")));
+        }
+        keyed.push_str(source);
+
+        hash_bytes(keyed.as_bytes())
+    }
+
+    /// The cached SPIR-V for a key, if every include it was compiled with still has the same
+    /// contents. `resolve` returns the current contents of an include, or None if it is gone.
+    pub fn load<F>(&self, key: u64, resolve: F) -> Option<Vec<u8>>
+    where
+        F: Fn(&Dependency) -> Option<String>,
+    {
+        let dependencies = fs::read_to_string(self.dependencies_path(key)).ok()?;
+
+        for line in dependencies.lines() {
+            let dependency = Dependency::from_line(line)?;
+            let content = resolve(&dependency)?;
+            if hash_bytes(content.as_bytes()) != dependency.hash {
+                return None;
+            }
+        }
+
+        fs::read(self.spirv_path(key)).ok()
+    }
+
+    pub fn store(&self, key: u64, spirv: &[u8], dependencies: &[Dependency]) {
+        let lines: Vec<String> = dependencies.iter().map(|d| d.to_line()).collect();
+
+        // the dependency list is removed first and written last, so a partly written entry is
+        // never loaded
+        let _ = fs::remove_file(self.dependencies_path(key));
+        let result = fs::write(self.spirv_path(key), spirv)
+            .and_then(|_| fs::write(self.dependencies_path(key), lines.join("\n")));
+
+        if let Err(e) = result {
+            println!("unable to write shader cache entry {:016x}: {}", key, e);
+        }
+    }
+
+    fn spirv_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.spv", key))
+    }
+
+    fn dependencies_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.deps", key))
+    }
+}
+
+/// 64-bit FNV-1a, which unlike the std hashers is stable across Rust releases.
+pub fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
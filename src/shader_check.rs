@@ -0,0 +1,319 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use nannou::prelude::*;
+
+use crate::compute::OUTPUT_IMAGE_NAME;
+use crate::effect_chain;
+use crate::effects;
+use crate::isf::{self, IsfEffect};
+use crate::reflect::{self, BindingKind, ShaderBinding};
+use crate::util::{self, FallbackShader, ShaderError};
+
+/// The subcommand that runs the check, e.g. `cargo run -- check-shaders [effect dirs...]`.
+pub const COMMAND: &str = "check-shaders";
+
+/// Shaders that aren't effect passes, so don't bind the chain's resources.
+const NON_EFFECT_SHADERS: &[&str] = &["default.vert", "layer.frag"];
+
+/// The result of checking one shader.
+struct Report {
+    file: String,
+    errors: Vec<String>,
+    notes: Vec<String>,
+}
+
+impl Report {
+    fn new(file: &str) -> Self {
+        Self {
+            file: file.to_string(),
+            errors: vec![],
+            notes: vec![],
+        }
+    }
+
+    fn failed(file: &str, error: ShaderError) -> Self {
+        let mut report = Self::new(file);
+        report.errors.push(error.to_string());
+        report
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(format!("{}: {}", self.file, message));
+    }
+
+    fn note(&mut self, message: String) {
+        self.notes.push(format!("{}: {}", self.file, message));
+    }
+
+    fn print(&self) {
+        if self.errors.is_empty() {
+            println!("ok      {}", self.file);
+        } else {
+            println!("FAILED  {}", self.file);
+        }
+        for error in self.errors.iter() {
+            println!("    error: {}", error);
+        }
+        for note in self.notes.iter() {
+            println!("    note: {}", note);
+        }
+    }
+}
+
+/// Compiles every shader in the shaders directory and in `effect_dirs`, along with the built-in
+/// fallbacks, and checks the bindings each effect declares against what an effect chain provides.
+/// Needs no GPU or camera, so it can run in CI. Returns whether every shader passed.
+///
+/// Includes in the effect directories resolve relative to the including file and then in the
+/// shader library. Bindings the chain doesn't provide itself are reported as notes, since they
+/// are only known once the effect is added to a chain with named inputs or storage buffers.
+pub fn run(effect_dirs: &[PathBuf]) -> bool {
+    let shaders_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("shaders");
+    let library_dir = shaders_dir.join(util::SHADER_LIBRARY_DIR);
+
+    let mut dirs = vec![shaders_dir];
+    dirs.extend(effect_dirs.iter().cloned());
+
+    let mut reports = vec![];
+
+    for dir in dirs.iter() {
+        let files = match shader_files(dir) {
+            Ok(files) => files,
+            Err(e) => {
+                let name = dir.to_string_lossy();
+                reports.push(Report::failed(
+                    &name,
+                    ShaderError::new(&name, e.to_string()),
+                ));
+                continue;
+            }
+        };
+
+        for filename in files {
+            if util::is_isf(&filename) {
+                reports.extend(check_isf_file(dir, &library_dir, &filename));
+            } else {
+                reports.push(check_file(dir, &library_dir, &filename));
+            }
+        }
+    }
+
+    for fallback in [FallbackShader::Error, FallbackShader::Passthrough] {
+        let filename = util::fallback_name(&fallback);
+        let report = util::compile_fallback_spirv(&fallback)
+            .and_then(|spirv| parse(filename, reflect::parse_spirv(&spirv)))
+            .map(|module| check_module(filename, &module))
+            .unwrap_or_else(|e| Report::failed(filename, e));
+        reports.push(report);
+    }
+
+    for report in reports.iter() {
+        report.print();
+    }
+
+    let failed = reports.iter().filter(|r| !r.errors.is_empty()).count();
+    println!("\n{} shaders checked, {} failed", reports.len(), failed);
+
+    failed == 0
+}
+
+/// Shader files under `dir`, relative to it, leaving out the shader library.
+fn shader_files(dir: &Path) -> io::Result<Vec<String>> {
+    let mut files = vec![];
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                if path != Path::new(util::SHADER_LIBRARY_DIR) {
+                    pending.push(path);
+                }
+                continue;
+            }
+
+            // include names use forward slashes on every platform
+            let filename = path.to_string_lossy().replace('\\', "/");
+            if util::is_wgsl(&filename)
+                || util::is_isf(&filename)
+                || util::shader_kind(&filename).is_some()
+            {
+                files.push(filename);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+fn check_file(dir: &Path, library_dir: &Path, filename: &str) -> Report {
+    let module = if util::is_wgsl(filename) {
+        fs::read_to_string(dir.join(filename))
+            .map_err(|e| ShaderError::new(filename, e.to_string()))
+            .and_then(|source| parse(filename, reflect::parse_wgsl(&source)))
+    } else {
+        let kind = util::shader_kind(filename).unwrap();
+        util::compile_spirv_in(dir, library_dir, None, filename, kind, &[])
+            .and_then(|spirv| parse(filename, reflect::parse_spirv(&spirv)))
+    };
+
+    match module {
+        Ok(module) => check_module(filename, &module),
+        Err(e) => Report::failed(filename, e),
+    }
+}
+
+/// Checks every pass of an imported ISF or Shadertoy effect.
+fn check_isf_file(dir: &Path, library_dir: &Path, filename: &str) -> Vec<Report> {
+    let effect = fs::read_to_string(dir.join(filename))
+        .map_err(|e| ShaderError::new(filename, e.to_string()))
+        .and_then(|source| {
+            IsfEffect::parse(&source).map_err(|e| ShaderError::new(filename, e.to_string()))
+        });
+
+    let effect = match effect {
+        Ok(effect) => effect,
+        Err(e) => return vec![Report::failed(filename, e)],
+    };
+
+    (0..effect.passes.len())
+        .map(|index| {
+            let name = match effect.passes.len() {
+                1 => filename.to_string(),
+                _ => format!("{} (pass {})", filename, index),
+            };
+
+            util::compile_glsl(
+                dir,
+                library_dir,
+                None,
+                filename,
+                &effect.pass_glsl(index),
+                shaderc::ShaderKind::Fragment,
+                &[],
+            )
+            .and_then(|spirv| parse(filename, reflect::parse_spirv(&spirv)))
+            .map(|module| check_module(&name, &module))
+            .unwrap_or_else(|e| Report::failed(&name, e))
+        })
+        .collect()
+}
+
+fn parse(
+    filename: &str,
+    module: Result<naga::Module, reflect::ReflectError>,
+) -> Result<naga::Module, ShaderError> {
+    module.map_err(|e| ShaderError::new(filename, e.to_string()))
+}
+
+fn check_module(filename: &str, module: &naga::Module) -> Report {
+    let mut report = Report::new(filename);
+
+    let checked = reflect::main_stage(module).and_then(|stage| {
+        reflect::validate_module(module)?;
+        Ok((stage, reflect::reflect_module(module)?))
+    });
+
+    match checked {
+        Ok((stage, bindings)) => {
+            let is_effect = !NON_EFFECT_SHADERS.contains(&filename);
+            match stage {
+                naga::ShaderStage::Fragment if is_effect => {
+                    check_bindings(&mut report, &bindings, false)
+                }
+                naga::ShaderStage::Compute if is_effect => {
+                    check_bindings(&mut report, &bindings, true)
+                }
+                _ => {}
+            }
+        }
+        Err(e) => report.error(e.to_string()),
+    }
+
+    report
+}
+
+/// Checks an effect's bindings against the resources an effect chain binds by name.
+fn check_bindings(report: &mut Report, bindings: &[ShaderBinding], compute: bool) {
+    // bindings are sorted by index
+    for pair in bindings.windows(2) {
+        if pair[0].binding == pair[1].binding {
+            report.error(format!(
+                "{} and {} are both at binding {}",
+                pair[0].name, pair[1].name, pair[0].binding
+            ));
+        }
+    }
+
+    for binding in bindings.iter() {
+        match effect_chain::builtin_binding_kind(&binding.name) {
+            Some(expected) if expected != binding.kind => report.error(format!(
+                "{} is declared as a {}, but the chain provides a {}",
+                binding.name,
+                describe(&binding.kind),
+                describe(&expected)
+            )),
+            Some(_) if binding.name == OUTPUT_IMAGE_NAME && !compute => report.error(format!(
+                "{} is only bound for compute passes",
+                OUTPUT_IMAGE_NAME
+            )),
+            Some(_) => {}
+            // the app binds the built-in effects' parameters and imported ISF inputs
+            None if is_parameter_block(&binding.name) => {
+                if binding.kind != BindingKind::UniformBuffer {
+                    report.error(format!(
+                        "{} is declared as a {}, but the app provides a uniform buffer",
+                        binding.name,
+                        describe(&binding.kind)
+                    ));
+                }
+            }
+            None => report.note(format!(
+                "{} {} at binding {} must be provided by the app{}",
+                describe(&binding.kind),
+                binding.name,
+                binding.binding,
+                match binding.kind {
+                    BindingKind::Texture | BindingKind::StorageTexture { .. } =>
+                        ", as a named input of the chain",
+                    BindingKind::StorageBuffer { .. } => ", as a storage buffer of the chain",
+                    BindingKind::UniformBuffer => ", as a uniform buffer of the chain",
+                    BindingKind::Sampler { .. } => "",
+                }
+            )),
+        }
+    }
+
+    if compute && !bindings.iter().any(|b| b.name == OUTPUT_IMAGE_NAME) {
+        report.error(format!(
+            "compute passes write their output to {}, which isn't declared",
+            OUTPUT_IMAGE_NAME
+        ));
+    }
+}
+
+/// Whether `name` is a uniform block the app binds to every pass: a built-in effect's
+/// parameters or an imported ISF effect's inputs.
+fn is_parameter_block(name: &str) -> bool {
+    name == isf::BLOCK_NAME || effects::Effect::ALL.iter().any(|e| e.block_name() == name)
+}
+
+fn describe(kind: &BindingKind) -> String {
+    match kind {
+        BindingKind::StorageBuffer { read_only: true } => "read-only storage buffer".to_string(),
+        BindingKind::StorageTexture { access } => match access {
+            wgpu::StorageTextureAccess::ReadOnly => "read-only storage texture".to_string(),
+            wgpu::StorageTextureAccess::WriteOnly => "write-only storage texture".to_string(),
+            wgpu::StorageTextureAccess::ReadWrite => "read-write storage texture".to_string(),
+        },
+        BindingKind::Sampler { comparison: true } => "comparison sampler".to_string(),
+        kind => kind.description().to_string(),
+    }
+}
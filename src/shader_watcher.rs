@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches a shader directory and its subdirectories for changes.
+/// Spawns a thread that polls file modification times and reports the names of changed files.
+pub struct ShaderWatcher {
+    change_receiver: Receiver<String>,
+    _worker_thread: thread::JoinHandle<()>,
+}
+
+impl ShaderWatcher {
+    pub fn new(directory: PathBuf) -> Self {
+        let (change_sender, change_receiver) = channel::<String>();
+
+        let worker_thread = thread::spawn(move || {
+            let mut modified_times = read_modified_times(&directory);
+
+            loop {
+                thread::sleep(POLL_INTERVAL);
+
+                let latest = read_modified_times(&directory);
+
+                for (filename, modified) in latest.iter() {
+                    if modified_times.get(filename) != Some(modified) {
+                        if change_sender.send(filename.clone()).is_err() {
+                            // the watcher has been dropped
+                            return;
+                        }
+                    }
+                }
+
+                modified_times = latest;
+            }
+        });
+
+        Self {
+            change_receiver,
+            _worker_thread: worker_thread,
+        }
+    }
+
+    /// Returns the names of the files that changed since the last call, without duplicates.
+    pub fn changed_files(&self) -> Vec<String> {
+        let mut changed: Vec<String> = vec![];
+
+        for filename in self.change_receiver.try_iter() {
+            if !changed.contains(&filename) {
+                changed.push(filename);
+            }
+        }
+
+        changed
+    }
+}
+
+fn read_modified_times(directory: &Path) -> HashMap<String, SystemTime> {
+    let mut times = HashMap::new();
+    read_modified_times_recursive(directory, directory, &mut times);
+    times
+}
+
+/// Collects modification times keyed by path relative to the watched directory, e.g. `lib/common.glsl`.
+fn read_modified_times_recursive(
+    root: &Path,
+    directory: &Path,
+    times: &mut HashMap<String, SystemTime>,
+) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Error reading shader directory: {:?}", e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            read_modified_times_recursive(root, &path, times);
+            continue;
+        }
+
+        let modified = match entry.metadata().and_then(|m| m.modified()) {
+            Ok(modified) => modified,
+            Err(_) => continue,
+        };
+
+        if let Ok(relative) = path.strip_prefix(root) {
+            let components: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            times.insert(components.join("/"), modified);
+        }
+    }
+}
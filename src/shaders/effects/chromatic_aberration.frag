@@ -0,0 +1,17 @@
+#version 450
+
+// Red and blue split away from a center, see effects::ChromaticAberrationParams.
+
+#include "effect.glsl"
+#include "chromatic_aberration_params.glsl"
+
+void main() {
+    // scaled so the offset is aberration_amount at the corners of a centered image
+    vec2 offset = (tex_coords - aberration_center) / 0.7071 * aberration_amount;
+
+    float r = sample_tex(tex_coords + offset).r;
+    float g = sample_tex(tex_coords).g;
+    float b = sample_tex(tex_coords - offset).b;
+
+    frag_color = vec4(r, g, b, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 450
+
+// Sobel edges, see effects::EdgeDetectParams.
+
+#include "effect.glsl"
+#include "filters.glsl"
+#include "edge_detect_params.glsl"
+
+void main() {
+    float gradient = sobel(tex_coords, 1.0 / tex_size()) * edge_strength;
+    float edge = smoothstep(edge_threshold, edge_threshold + 0.1, gradient) * edge_color.a;
+
+    vec3 base = sample_tex(tex_coords).rgb * edge_overlay;
+
+    frag_color = vec4(mix(base, edge_color.rgb, edge), 1.0);
+}
@@ -0,0 +1,32 @@
+#version 450
+
+// Ink dots on paper, larger where the image is darker, see effects::HalftoneParams.
+
+#include "effect.glsl"
+#include "filters.glsl"
+#include "halftone_params.glsl"
+
+void main() {
+    vec2 size = tex_size();
+    float dot_size = max(halftone_dot_size, 1.0);
+
+    float c = cos(halftone_angle);
+    float s = sin(halftone_angle);
+    mat2 rotation = mat2(c, s, -s, c);
+
+    // position in the rotated dot grid, in cells
+    vec2 grid = rotation * (tex_coords * size) / dot_size;
+    vec2 cell = floor(grid) + 0.5;
+
+    // sample the image once per cell, at its center
+    vec2 cell_uv = transpose(rotation) * (cell * dot_size) / size;
+    float darkness = 1.0 - luminance(sample_tex(cell_uv).rgb);
+
+    // the dot's area follows the darkness, and at full darkness the dots cover the cell
+    float radius = sqrt(darkness) * 0.7071;
+    float dist = length(grid - cell);
+    float aa = fwidth(dist);
+    float ink = 1.0 - smoothstep(radius - aa, radius + aa, dist);
+
+    frag_color = vec4(mix(halftone_paper.rgb, halftone_ink.rgb, ink * halftone_ink.a), 1.0);
+}
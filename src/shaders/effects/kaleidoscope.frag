@@ -0,0 +1,33 @@
+#version 450
+
+// Mirrored wedges around a center, see effects::KaleidoscopeParams.
+
+#include "effect.glsl"
+#include "filters.glsl"
+#include "kaleidoscope_params.glsl"
+
+void main() {
+    vec2 size = tex_size();
+    vec2 aspect = vec2(size.x / size.y, 1.0);
+
+    vec2 center = kaleidoscope_center;
+    if (kaleidoscope_follow_face != 0u && face_present != 0u) {
+        center = face_center;
+    }
+
+    // work in a square space so the wedges aren't stretched
+    vec2 p = (tex_coords - center) * aspect;
+    float radius = length(p);
+    float segment = TAU / max(kaleidoscope_segments, 1.0);
+
+    // fold every wedge onto the first one, reflecting every other wedge
+    float angle = mod(atan(p.y, p.x) - kaleidoscope_rotation, segment);
+    angle = min(angle, segment - angle) + kaleidoscope_rotation;
+
+    vec2 uv = center + vec2(cos(angle), sin(angle)) * radius / aspect;
+
+    // reflect at the edges rather than stretching the border pixels
+    uv = 1.0 - abs(1.0 - mod(uv, 2.0));
+
+    frag_color = vec4(sample_tex(uv).rgb, 1.0);
+}
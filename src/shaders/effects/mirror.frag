@@ -0,0 +1,32 @@
+#version 450
+
+// One half of the image reflected onto the other, see effects::MirrorParams.
+
+#include "effect.glsl"
+#include "mirror_params.glsl"
+
+float reflect_above(float x, float axis) {
+    return x > axis ? 2.0 * axis - x : x;
+}
+
+float reflect_below(float x, float axis) {
+    return x < axis ? 2.0 * axis - x : x;
+}
+
+void main() {
+    vec2 uv = tex_coords;
+
+    if (mirror_mode == MIRROR_LEFT_TO_RIGHT) {
+        uv.x = reflect_above(uv.x, mirror_axis);
+    } else if (mirror_mode == MIRROR_RIGHT_TO_LEFT) {
+        uv.x = reflect_below(uv.x, mirror_axis);
+    } else if (mirror_mode == MIRROR_TOP_TO_BOTTOM) {
+        uv.y = reflect_above(uv.y, mirror_axis);
+    } else if (mirror_mode == MIRROR_BOTTOM_TO_TOP) {
+        uv.y = reflect_below(uv.y, mirror_axis);
+    } else if (mirror_mode == MIRROR_QUAD) {
+        uv = vec2(reflect_above(uv.x, mirror_axis), reflect_above(uv.y, mirror_axis));
+    }
+
+    frag_color = vec4(sample_tex(clamp(uv, 0.0, 1.0)).rgb, 1.0);
+}
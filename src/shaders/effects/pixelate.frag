@@ -0,0 +1,14 @@
+#version 450
+
+// Square blocks of a single color, see effects::PixelateParams.
+
+#include "effect.glsl"
+#include "filters.glsl"
+#include "pixelate_params.glsl"
+
+void main() {
+    vec2 block = max(pixel_size, 1.0) / tex_size();
+    vec2 uv = (floor(tex_coords / block) + 0.5) * block;
+
+    frag_color = vec4(sample_tex(uv).rgb, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+
+// Quantized color levels, see effects::PosterizeParams.
+
+#include "effect.glsl"
+#include "posterize_params.glsl"
+
+void main() {
+    vec3 color = sample_tex(tex_coords).rgb;
+    float levels = max(posterize_levels, 2.0);
+    float gamma = max(posterize_gamma, 0.01);
+
+    vec3 quantized = pow(color, vec3(gamma));
+    quantized = min(floor(quantized * levels), levels - 1.0) / (levels - 1.0);
+    quantized = pow(quantized, vec3(1.0 / gamma));
+
+    frag_color = vec4(quantized, 1.0);
+}
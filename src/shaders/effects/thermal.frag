@@ -0,0 +1,30 @@
+#version 450
+
+// Brightness mapped onto a thermal camera palette, see effects::ThermalParams.
+
+#include "effect.glsl"
+#include "filters.glsl"
+#include "thermal_params.glsl"
+
+const vec3 PALETTE[7] = vec3[7](
+    vec3(0.0, 0.0, 0.0),
+    vec3(0.1, 0.0, 0.5),
+    vec3(0.55, 0.0, 0.6),
+    vec3(0.9, 0.1, 0.2),
+    vec3(1.0, 0.5, 0.0),
+    vec3(1.0, 0.9, 0.1),
+    vec3(1.0, 1.0, 1.0)
+);
+
+vec3 thermal_palette(float heat) {
+    float x = clamp(heat, 0.0, 1.0) * 6.0;
+    int i = int(min(floor(x), 5.0));
+    return mix(PALETTE[i], PALETTE[i + 1], x - float(i));
+}
+
+void main() {
+    float brightness = luminance(sample_tex(tex_coords).rgb);
+    float heat = (brightness - 0.5) * thermal_contrast + 0.5 + thermal_offset;
+
+    frag_color = vec4(thermal_palette(heat), 1.0);
+}
@@ -0,0 +1,29 @@
+#version 450
+
+// Flat shading bands with ink outlines, see effects::ToonParams.
+
+#include "effect.glsl"
+#include "filters.glsl"
+#include "toon_params.glsl"
+
+void main() {
+    vec2 offset = max(toon_edge_width, 0.5) / tex_size();
+
+    // soften the image a little so the bands don't pick up camera noise
+    vec3 color = sample_tex(tex_coords).rgb * 0.5
+        + sample_tex(tex_coords + offset * vec2(1.0, 0.0)).rgb * 0.125
+        + sample_tex(tex_coords - offset * vec2(1.0, 0.0)).rgb * 0.125
+        + sample_tex(tex_coords + offset * vec2(0.0, 1.0)).rgb * 0.125
+        + sample_tex(tex_coords - offset * vec2(0.0, 1.0)).rgb * 0.125;
+
+    float levels = max(toon_levels, 2.0);
+    float brightness = luminance(color);
+    float banded = (min(floor(brightness * levels), levels - 1.0) + 0.5) / levels;
+
+    vec3 shaded = color / max(brightness, 0.001) * banded;
+    shaded = clamp(mix(vec3(banded), shaded, toon_saturation), 0.0, 1.0);
+
+    float edge = step(toon_edge_threshold, sobel(tex_coords, offset));
+
+    frag_color = vec4(shaded * (1.0 - edge), 1.0);
+}
@@ -0,0 +1,35 @@
+#version 450
+
+// Draws one compositor layer, see compositor::Compositor.
+
+layout(location = 0) in vec2 tex_coords;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform texture2D layer_tex;
+layout(set = 0, binding = 1) uniform sampler layer_sampler;
+
+#include "layer.glsl"
+
+void main() {
+    vec2 uv = (layer_transform * vec4(tex_coords, 0.0, 1.0)).xy;
+
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+        discard;
+    }
+
+    vec4 color = texture(sampler2D(layer_tex, layer_sampler), uv);
+
+    // Multiply and Screen blend premultiplied colours, see render::BlendMode
+    if (layer_premultiply != 0u) {
+        color.rgb *= color.a;
+    }
+
+    // premultiplied and Replace layers fade to transparent black
+    if (layer_premultiplied != 0u) {
+        color *= layer_opacity;
+    } else {
+        color.a *= layer_opacity;
+    }
+
+    f_color = color;
+}
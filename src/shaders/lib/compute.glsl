@@ -0,0 +1,74 @@
+// Inputs and outputs of an effect chain compute pass.
+// Each invocation handles one pixel of the output, in workgroups of 8x8 (compute::WORKGROUP_SIZE).
+// Bindings work the same as in effect.glsl: declare named inputs and storage buffers
+// (e.g. particle state) under their names at EFFECT_USER_BINDING or above.
+// EFFECT_HISTORY_FRAMES works the same as in effect.glsl.
+
+#ifndef COMPUTE_GLSL
+#define COMPUTE_GLSL
+
+#ifndef EFFECT_HISTORY_FRAMES
+#define EFFECT_HISTORY_FRAMES 0
+#endif
+
+#define LANDMARKS_BINDING 0
+#define SAMPLER_BINDING 3
+#define UNIFORMS_BINDING 4
+#define HISTORY_BINDING 5
+#define OUTPUT_BINDING 9
+#define EFFECT_USER_BINDING 16
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+// the previous pass's output, or the video for the first pass
+layout(set = 0, binding = 1) uniform texture2D tex;
+layout(set = 0, binding = 2) uniform texture2D video;
+layout(set = 0, binding = OUTPUT_BINDING, rgba16f) uniform writeonly image2D output_image;
+layout(set = 0, binding = SAMPLER_BINDING) uniform sampler tex_sampler;
+
+#if EFFECT_HISTORY_FRAMES > 0
+layout(set = 0, binding = HISTORY_BINDING) uniform texture2D previous_frame;
+#endif
+#if EFFECT_HISTORY_FRAMES > 1
+layout(set = 0, binding = HISTORY_BINDING + 1) uniform texture2D previous_frame_2;
+#endif
+#if EFFECT_HISTORY_FRAMES > 2
+layout(set = 0, binding = HISTORY_BINDING + 2) uniform texture2D previous_frame_3;
+#endif
+#if EFFECT_HISTORY_FRAMES > 3
+layout(set = 0, binding = HISTORY_BINDING + 3) uniform texture2D previous_frame_4;
+#endif
+
+#include "landmarks.glsl"
+#include "uniforms.glsl"
+
+// The pixel handled by this invocation, and whether it is inside the output.
+ivec2 pixel_coords() {
+    return ivec2(gl_GlobalInvocationID.xy);
+}
+
+bool in_bounds(ivec2 coords) {
+    return all(lessThan(coords, imageSize(output_image)));
+}
+
+// The texture coordinates of the centre of a pixel.
+vec2 pixel_uv(ivec2 coords) {
+    return (vec2(coords) + 0.5) / vec2(imageSize(output_image));
+}
+
+// compute shaders have no derivatives, so sample the base level explicitly
+vec4 sample_tex(vec2 uv) {
+    return textureLod(sampler2D(tex, tex_sampler), uv, 0.0);
+}
+
+vec4 sample_video(vec2 uv) {
+    return textureLod(sampler2D(video, tex_sampler), uv, 0.0);
+}
+
+#if EFFECT_HISTORY_FRAMES > 0
+vec4 sample_previous_frame(vec2 uv) {
+    return textureLod(sampler2D(previous_frame, tex_sampler), uv, 0.0);
+}
+#endif
+
+#endif
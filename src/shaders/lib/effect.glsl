@@ -0,0 +1,64 @@
+// Inputs and outputs of an effect chain pass.
+// Resources are bound by name, so the binding numbers below only need to be unique.
+// Declare the chain's named inputs and storage buffers under their names at binding
+// EFFECT_USER_BINDING or above, in any order.
+// The built-in effects in effects/ include their parameter block at EFFECT_PARAMS_BINDING,
+// which can be defined before including this file to move it.
+// Define EFFECT_HISTORY_FRAMES to the pass's number of history frames (at most 4) to sample
+// its previous outputs as previous_frame, previous_frame_2 and so on.
+
+#ifndef EFFECT_GLSL
+#define EFFECT_GLSL
+
+#ifndef EFFECT_HISTORY_FRAMES
+#define EFFECT_HISTORY_FRAMES 0
+#endif
+
+#define LANDMARKS_BINDING 0
+#define SAMPLER_BINDING 3
+#define UNIFORMS_BINDING 4
+#define HISTORY_BINDING 5
+#ifndef EFFECT_PARAMS_BINDING
+#define EFFECT_PARAMS_BINDING 10
+#endif
+#define EFFECT_USER_BINDING 16
+
+layout(location = 0) in vec2 tex_coords;
+layout(location = 0) out vec4 frag_color;
+
+// the previous pass's output, or the video for the first pass
+layout(set = 0, binding = 1) uniform texture2D tex;
+layout(set = 0, binding = 2) uniform texture2D video;
+layout(set = 0, binding = SAMPLER_BINDING) uniform sampler tex_sampler;
+
+#if EFFECT_HISTORY_FRAMES > 0
+layout(set = 0, binding = HISTORY_BINDING) uniform texture2D previous_frame;
+#endif
+#if EFFECT_HISTORY_FRAMES > 1
+layout(set = 0, binding = HISTORY_BINDING + 1) uniform texture2D previous_frame_2;
+#endif
+#if EFFECT_HISTORY_FRAMES > 2
+layout(set = 0, binding = HISTORY_BINDING + 2) uniform texture2D previous_frame_3;
+#endif
+#if EFFECT_HISTORY_FRAMES > 3
+layout(set = 0, binding = HISTORY_BINDING + 3) uniform texture2D previous_frame_4;
+#endif
+
+#include "landmarks.glsl"
+#include "uniforms.glsl"
+
+vec4 sample_tex(vec2 uv) {
+    return texture(sampler2D(tex, tex_sampler), uv);
+}
+
+vec4 sample_video(vec2 uv) {
+    return texture(sampler2D(video, tex_sampler), uv);
+}
+
+#if EFFECT_HISTORY_FRAMES > 0
+vec4 sample_previous_frame(vec2 uv) {
+    return texture(sampler2D(previous_frame, tex_sampler), uv);
+}
+#endif
+
+#endif
@@ -0,0 +1,31 @@
+// Helpers shared by the built-in effects. Include after effect.glsl.
+// Builds on common.glsl, which it includes for PI, TAU and luminance().
+
+#ifndef FILTERS_GLSL
+#define FILTERS_GLSL
+
+#include "common.glsl"
+
+// the size of the previous pass's output in pixels
+vec2 tex_size() {
+    return vec2(textureSize(sampler2D(tex, tex_sampler), 0));
+}
+
+// the magnitude of the Sobel gradient of the previous pass's luminance, with taps `offset` apart
+float sobel(vec2 uv, vec2 offset) {
+    float tl = luminance(sample_tex(uv + offset * vec2(-1.0, -1.0)).rgb);
+    float t = luminance(sample_tex(uv + offset * vec2(0.0, -1.0)).rgb);
+    float tr = luminance(sample_tex(uv + offset * vec2(1.0, -1.0)).rgb);
+    float l = luminance(sample_tex(uv + offset * vec2(-1.0, 0.0)).rgb);
+    float r = luminance(sample_tex(uv + offset * vec2(1.0, 0.0)).rgb);
+    float bl = luminance(sample_tex(uv + offset * vec2(-1.0, 1.0)).rgb);
+    float b = luminance(sample_tex(uv + offset * vec2(0.0, 1.0)).rgb);
+    float br = luminance(sample_tex(uv + offset * vec2(1.0, 1.0)).rgb);
+
+    float gx = -tl - 2.0 * l - bl + tr + 2.0 * r + br;
+    float gy = -tl - 2.0 * t - tr + bl + 2.0 * b + br;
+
+    return length(vec2(gx, gy));
+}
+
+#endif
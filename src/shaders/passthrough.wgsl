@@ -0,0 +1,14 @@
+// A WGSL effect. WGSL has no includes, so declare the resources the pass uses yourself.
+// They are bound by name like in effect.glsl: tex, video, tex_sampler, previous_frame and so
+// on, with buffers named by their struct, e.g. Uniforms.
+
+[[group(0), binding(1)]]
+var tex: texture_2d<f32>;
+[[group(0), binding(3)]]
+var tex_sampler: sampler;
+
+[[stage(fragment)]]
+fn main([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(tex, tex_sampler, tex_coords).rgb;
+    return vec4<f32>(color, 1.0);
+}
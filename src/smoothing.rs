@@ -0,0 +1,188 @@
+use std::f32::consts::TAU;
+use std::time::Instant;
+
+use mediapipe::holistic::HolisticDetection;
+
+/// Shortest time step the filters use, so frames captured at the same instant don't divide by 0.
+const MIN_TIME_STEP: f32 = 1.0 / 1000.0;
+
+/// How landmarks are smoothed between detections.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smoothing {
+    Off,
+    /// Exponential moving average. Each update moves `alpha` of the way to the new position,
+    /// so lower values are smoother but lag more.
+    Ema {
+        alpha: f32,
+    },
+    /// The One Euro filter: a low-pass filter whose cutoff rises with speed, so slow movement
+    /// is smoothed heavily and fast movement lags little. Cutoffs are in Hz and `beta` scales
+    /// the speed, which is in normalized coordinates per second.
+    OneEuro {
+        min_cutoff: f32,
+        beta: f32,
+        derivative_cutoff: f32,
+    },
+}
+
+impl Smoothing {
+    pub fn one_euro(min_cutoff: f32, beta: f32) -> Self {
+        Smoothing::OneEuro {
+            min_cutoff,
+            beta,
+            derivative_cutoff: 1.0,
+        }
+    }
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing::one_euro(1.0, 10.0)
+    }
+}
+
+/// Smooths the face, pose and hand landmarks of successive holistic detections.
+///
+/// Each group is filtered independently and starts over when it stops being detected, so a
+/// hand that reappears elsewhere isn't dragged in from where it was lost.
+#[derive(Default)]
+pub struct LandmarkSmoother {
+    smoothing: Smoothing,
+    face: GroupFilter,
+    pose: GroupFilter,
+    left_hand: GroupFilter,
+    right_hand: GroupFilter,
+}
+
+impl LandmarkSmoother {
+    pub fn smoothing(&self) -> Smoothing {
+        self.smoothing
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        self.smoothing = smoothing;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.face.reset();
+        self.pose.reset();
+        self.left_hand.reset();
+        self.right_hand.reset();
+    }
+
+    /// Smooths the detection in place. `time` is when its frame was captured.
+    pub fn apply(&mut self, detection: &mut HolisticDetection, time: Instant) {
+        let smoothing = self.smoothing;
+
+        match &mut detection.face {
+            Some(face) => self.face.apply(&smoothing, &mut face.data, time),
+            None => self.face.reset(),
+        }
+
+        match &mut detection.pose {
+            Some(pose) => self.pose.apply(&smoothing, &mut pose.data, time),
+            None => self.pose.reset(),
+        }
+
+        match &mut detection.left_hand {
+            Some(hand) => self.left_hand.apply(&smoothing, &mut hand.data, time),
+            None => self.left_hand.reset(),
+        }
+
+        match &mut detection.right_hand {
+            Some(hand) => self.right_hand.apply(&smoothing, &mut hand.data, time),
+            None => self.right_hand.reset(),
+        }
+    }
+}
+
+/// The filtered value of one coordinate, and for the One Euro filter its filtered speed.
+#[derive(Clone, Copy, Default)]
+struct ChannelState {
+    value: f32,
+    derivative: f32,
+}
+
+/// Filter state for a group of landmarks: x, y and z of each.
+#[derive(Default)]
+struct GroupFilter {
+    states: Vec<[ChannelState; 3]>,
+    last_time: Option<Instant>,
+}
+
+impl GroupFilter {
+    fn reset(&mut self) {
+        self.states.clear();
+        self.last_time = None;
+    }
+
+    fn apply(
+        &mut self,
+        smoothing: &Smoothing,
+        landmarks: &mut [mediapipe::Landmark],
+        time: Instant,
+    ) {
+        if *smoothing == Smoothing::Off {
+            return;
+        }
+
+        let last_time = self.last_time.replace(time);
+
+        // the first detection after a reset, or one with a different landmark count
+        // (e.g. a face mesh with irises), starts the filter from its positions
+        if last_time.is_none() || self.states.len() != landmarks.len() {
+            self.states = landmarks
+                .iter()
+                .map(|l| {
+                    [l.x, l.y, l.z].map(|value| ChannelState {
+                        value,
+                        derivative: 0.0,
+                    })
+                })
+                .collect();
+            return;
+        }
+
+        let dt = time
+            .saturating_duration_since(last_time.unwrap())
+            .as_secs_f32()
+            .max(MIN_TIME_STEP);
+
+        for (states, landmark) in self.states.iter_mut().zip(landmarks.iter_mut()) {
+            let values = [&mut landmark.x, &mut landmark.y, &mut landmark.z];
+            for (state, value) in states.iter_mut().zip(values) {
+                *value = filter(smoothing, state, *value, dt);
+            }
+        }
+    }
+}
+
+fn filter(smoothing: &Smoothing, state: &mut ChannelState, value: f32, dt: f32) -> f32 {
+    match *smoothing {
+        Smoothing::Off => return value,
+        Smoothing::Ema { alpha } => {
+            state.value += alpha.clamp(0.0, 1.0) * (value - state.value);
+        }
+        Smoothing::OneEuro {
+            min_cutoff,
+            beta,
+            derivative_cutoff,
+        } => {
+            let derivative = (value - state.value) / dt;
+            state.derivative +=
+                smoothing_factor(derivative_cutoff, dt) * (derivative - state.derivative);
+
+            let cutoff = min_cutoff + beta * state.derivative.abs();
+            state.value += smoothing_factor(cutoff, dt) * (value - state.value);
+        }
+    }
+
+    state.value
+}
+
+/// The weight of a new sample for a low-pass filter with the given cutoff frequency.
+fn smoothing_factor(cutoff: f32, dt: f32) -> f32 {
+    let tau = 1.0 / (TAU * cutoff.max(f32::EPSILON));
+    1.0 / (1.0 + tau / dt)
+}
@@ -0,0 +1,445 @@
+use std::fmt::Write;
+
+/// A GLSL type that can appear in a std140 uniform block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformType {
+    Float,
+    Int,
+    Uint,
+    Vec2,
+    Vec3,
+    Vec4,
+    Mat4,
+}
+
+impl UniformType {
+    pub fn glsl_name(&self) -> &'static str {
+        match self {
+            UniformType::Float => "float",
+            UniformType::Int => "int",
+            UniformType::Uint => "uint",
+            UniformType::Vec2 => "vec2",
+            UniformType::Vec3 => "vec3",
+            UniformType::Vec4 => "vec4",
+            UniformType::Mat4 => "mat4",
+        }
+    }
+
+    /// The base alignment in bytes under the std140 rules.
+    pub fn alignment(&self) -> usize {
+        match self {
+            UniformType::Float | UniformType::Int | UniformType::Uint => 4,
+            UniformType::Vec2 => 8,
+            UniformType::Vec3 | UniformType::Vec4 | UniformType::Mat4 => 16,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            UniformType::Float | UniformType::Int | UniformType::Uint => 4,
+            UniformType::Vec2 => 8,
+            UniformType::Vec3 => 12,
+            UniformType::Vec4 => 16,
+            UniformType::Mat4 => 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UniformField {
+    pub name: String,
+    pub ty: UniformType,
+    pub offset: usize,
+    /// The number of elements if the field is an array.
+    pub count: Option<usize>,
+}
+
+impl UniformField {
+    /// The distance between array elements: std140 rounds each element up to a vec4.
+    pub fn stride(&self) -> usize {
+        match self.count {
+            Some(_) => align_to(self.ty.size(), 16),
+            None => self.ty.size(),
+        }
+    }
+
+    /// The number of bytes the field covers.
+    pub fn size(&self) -> usize {
+        match self.count {
+            Some(count) => self.stride() * count,
+            None => self.ty.size(),
+        }
+    }
+}
+
+/// The std140 layout of a uniform block.
+#[derive(Debug, Clone)]
+pub struct UniformLayout {
+    pub name: String,
+    pub fields: Vec<UniformField>,
+    pub size: usize,
+}
+
+pub struct UniformLayoutBuilder {
+    name: String,
+    fields: Vec<UniformField>,
+    offset: usize,
+}
+
+impl UniformLayoutBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            fields: vec![],
+            offset: 0,
+        }
+    }
+
+    pub fn field(self, name: &str, ty: UniformType) -> Self {
+        self.push(name, ty, None)
+    }
+
+    /// Adds an array field. std140 aligns arrays, and each of their elements, like a vec4.
+    pub fn array(self, name: &str, ty: UniformType, count: usize) -> Self {
+        self.push(name, ty, Some(count))
+    }
+
+    fn push(mut self, name: &str, ty: UniformType, count: Option<usize>) -> Self {
+        if self.fields.iter().any(|f| f.name == name) {
+            panic!("uniform block {} declares {} twice", self.name, name);
+        }
+
+        let alignment = match count {
+            Some(_) => align_to(ty.alignment(), 16),
+            None => ty.alignment(),
+        };
+
+        let field = UniformField {
+            name: name.to_string(),
+            ty,
+            offset: align_to(self.offset, alignment),
+            count,
+        };
+        self.offset = field.offset + field.size();
+        self.fields.push(field);
+        self
+    }
+
+    pub fn build(self) -> UniformLayout {
+        UniformLayout {
+            name: self.name,
+            fields: self.fields,
+            // the block as a whole is aligned like a vec4
+            size: align_to(self.offset, 16),
+        }
+    }
+}
+
+impl UniformLayout {
+    pub fn builder(name: &str) -> UniformLayoutBuilder {
+        UniformLayoutBuilder::new(name)
+    }
+
+    /// The GLSL declaration of this block with an explicit `offset` on every member.
+    /// This pins the shader's layout to the offsets computed here, and shaderc rejects
+    /// any offset that breaks the std140 alignment rules.
+    pub fn glsl_block(&self, set: u32, binding: &str) -> String {
+        let mut glsl = String::new();
+        writeln!(
+            glsl,
+            "layout(std140, set = {}, binding = {}) uniform {} {{",
+            set, binding, self.name
+        )
+        .unwrap();
+        for field in self.fields.iter() {
+            let array = field.count.map(|c| format!("[{}]", c)).unwrap_or_default();
+            writeln!(
+                glsl,
+                "    layout(offset = {}) {} {}{};",
+                field.offset,
+                field.ty.glsl_name(),
+                field.name,
+                array
+            )
+            .unwrap();
+        }
+        glsl.push_str("};\n");
+        glsl
+    }
+
+    pub fn writer(&self) -> Std140Writer {
+        Std140Writer {
+            layout: self,
+            bytes: vec![0; self.size],
+            index: 0,
+        }
+    }
+}
+
+/// A value that can be written into a std140 uniform block.
+pub trait Std140Value {
+    const TYPE: UniformType;
+
+    fn write_bytes(&self, bytes: &mut [u8]);
+}
+
+impl Std140Value for f32 {
+    const TYPE: UniformType = UniformType::Float;
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        bytes[..4].copy_from_slice(&self.to_ne_bytes());
+    }
+}
+
+impl Std140Value for i32 {
+    const TYPE: UniformType = UniformType::Int;
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        bytes[..4].copy_from_slice(&self.to_ne_bytes());
+    }
+}
+
+impl Std140Value for u32 {
+    const TYPE: UniformType = UniformType::Uint;
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        bytes[..4].copy_from_slice(&self.to_ne_bytes());
+    }
+}
+
+impl Std140Value for bool {
+    const TYPE: UniformType = UniformType::Uint;
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        (*self as u32).write_bytes(bytes);
+    }
+}
+
+impl Std140Value for [f32; 2] {
+    const TYPE: UniformType = UniformType::Vec2;
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        write_floats(self, bytes);
+    }
+}
+
+impl Std140Value for [f32; 3] {
+    const TYPE: UniformType = UniformType::Vec3;
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        write_floats(self, bytes);
+    }
+}
+
+impl Std140Value for [f32; 4] {
+    const TYPE: UniformType = UniformType::Vec4;
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        write_floats(self, bytes);
+    }
+}
+
+/// A column major 4x4 matrix.
+impl Std140Value for [[f32; 4]; 4] {
+    const TYPE: UniformType = UniformType::Mat4;
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        for (i, column) in self.iter().enumerate() {
+            write_floats(column, &mut bytes[i * 16..]);
+        }
+    }
+}
+
+/// Writes the fields of a uniform block in declaration order, arrays with `write_array`.
+/// Panics if a value does not match the type of the field it is written to,
+/// or if the number of values does not match the number of fields.
+pub struct Std140Writer<'a> {
+    layout: &'a UniformLayout,
+    bytes: Vec<u8>,
+    index: usize,
+}
+
+impl<'a> Std140Writer<'a> {
+    pub fn write<V: Std140Value>(&mut self, value: V) -> &mut Self {
+        let field = self.next_field::<V>(None);
+
+        value.write_bytes(&mut self.bytes[field.offset..field.offset + field.ty.size()]);
+        self.index += 1;
+        self
+    }
+
+    /// Writes an array field, which must be given exactly as many values as it holds.
+    pub fn write_array<V: Std140Value>(&mut self, values: &[V]) -> &mut Self {
+        let field = self.next_field::<V>(Some(values.len()));
+
+        for (i, value) in values.iter().enumerate() {
+            let offset = field.offset + i * field.stride();
+            value.write_bytes(&mut self.bytes[offset..offset + field.ty.size()]);
+        }
+        self.index += 1;
+        self
+    }
+
+    fn next_field<V: Std140Value>(&self, count: Option<usize>) -> UniformField {
+        let field = match self.layout.fields.get(self.index) {
+            Some(field) => field,
+            None => panic!(
+                "uniform block {} has {} fields but more values were written",
+                self.layout.name,
+                self.layout.fields.len()
+            ),
+        };
+
+        if field.ty != V::TYPE || field.count != count {
+            let describe = |count: Option<usize>| match count {
+                Some(count) => format!("[{}]", count),
+                None => String::new(),
+            };
+            panic!(
+                "uniform block {} field {} is a {}{} but a {}{} was written",
+                self.layout.name,
+                field.name,
+                field.ty.glsl_name(),
+                describe(field.count),
+                V::TYPE.glsl_name(),
+                describe(count)
+            );
+        }
+
+        field.clone()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        if self.index != self.layout.fields.len() {
+            panic!(
+                "uniform block {} has {} fields but only {} values were written",
+                self.layout.name,
+                self.layout.fields.len(),
+                self.index
+            );
+        }
+
+        self.bytes
+    }
+}
+
+/// A Rust type that is uploaded as a std140 uniform block.
+pub trait UniformBlock {
+    fn layout() -> UniformLayout;
+
+    /// Writes every field, in the order they are declared in `layout`.
+    fn write(&self, writer: &mut Std140Writer);
+
+    fn std140_bytes(&self) -> Vec<u8> {
+        let layout = Self::layout();
+        let mut writer = layout.writer();
+        self.write(&mut writer);
+        writer.finish()
+    }
+}
+
+fn write_floats(values: &[f32], bytes: &mut [u8]) {
+    for (i, value) in values.iter().enumerate() {
+        bytes[i * 4..i * 4 + 4].copy_from_slice(&value.to_ne_bytes());
+    }
+}
+
+fn align_to(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(layout: &UniformLayout) -> Vec<usize> {
+        layout.fields.iter().map(|f| f.offset).collect()
+    }
+
+    #[test]
+    fn float_packs_after_vec3() {
+        let layout = UniformLayout::builder("Block")
+            .field("direction", UniformType::Vec3)
+            .field("strength", UniformType::Float)
+            .field("tint", UniformType::Vec3)
+            .build();
+
+        assert_eq!(offsets(&layout), [0, 12, 16]);
+        assert_eq!(layout.size, 32);
+    }
+
+    #[test]
+    fn mat4_is_aligned_like_a_vec4() {
+        let layout = UniformLayout::builder("Block")
+            .field("time", UniformType::Float)
+            .field("transform", UniformType::Mat4)
+            .field("frame", UniformType::Uint)
+            .build();
+
+        assert_eq!(offsets(&layout), [0, 16, 80]);
+        assert_eq!(layout.size, 96);
+    }
+
+    #[test]
+    fn array_elements_are_padded_to_16_bytes() {
+        let layout = UniformLayout::builder("Block")
+            .field("count", UniformType::Uint)
+            .array("weights", UniformType::Float, 3)
+            .array("points", UniformType::Vec2, 2)
+            .field("last", UniformType::Float)
+            .build();
+
+        assert_eq!(offsets(&layout), [0, 16, 64, 96]);
+        assert_eq!(layout.fields[1].stride(), 16);
+        assert_eq!(layout.fields[2].stride(), 16);
+        assert!(layout
+            .glsl_block(0, "0")
+            .contains("layout(offset = 16) float weights[3];"));
+
+        let mut writer = layout.writer();
+        writer
+            .write(3u32)
+            .write_array(&[1.0f32, 2.0, 3.0])
+            .write_array(&[[4.0f32, 5.0], [6.0, 7.0]])
+            .write(8.0f32);
+        let bytes = writer.finish();
+
+        let float_at =
+            |offset: usize| f32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        assert_eq!(float_at(32), 2.0);
+        assert_eq!(float_at(48), 3.0);
+        assert_eq!(float_at(80), 6.0);
+        assert_eq!(float_at(96), 8.0);
+    }
+
+    #[test]
+    fn block_size_is_rounded_up_to_16() {
+        let layout = UniformLayout::builder("Block")
+            .field("a", UniformType::Float)
+            .field("b", UniformType::Vec2)
+            .build();
+
+        assert_eq!(offsets(&layout), [0, 8]);
+        assert_eq!(layout.size, 16);
+
+        let empty = UniformLayout::builder("Empty").build();
+        assert_eq!(empty.size, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "declares a twice")]
+    fn duplicate_fields_panic() {
+        UniformLayout::builder("Block")
+            .field("a", UniformType::Float)
+            .field("a", UniformType::Float);
+    }
+
+    #[test]
+    #[should_panic(expected = "is a float but a vec2 was written")]
+    fn mismatched_values_panic() {
+        let layout = UniformLayout::builder("Block")
+            .field("a", UniformType::Float)
+            .build();
+        layout.writer().write([0.0f32, 1.0]);
+    }
+}
@@ -1,59 +1,333 @@
 use nannou::prelude::*;
 
-#[repr(C)]
+use crate::holistic_detector::HolisticDetector;
+use crate::std140::*;
+
+/// The name shaders include the generated `Uniforms` block declaration by.
+pub const GLSL_INCLUDE_NAME: &str = "uniforms.glsl";
+
+pub const MOUSE_LEFT: u32 = 1;
+pub const MOUSE_RIGHT: u32 = 2;
+pub const MOUSE_MIDDLE: u32 = 4;
+
+/// The standard uniform block shared by every effect.
+/// Positions are in texture coordinates: (0, 0) is the top left and (1, 1) the bottom right.
+/// Shaders get the matching declaration with `#include "uniforms.glsl"`, see `glsl_include`.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Uniforms {
-    pub width: f32,
-    pub height: f32,
+    pub time: f32,
+    pub delta_time: f32,
+    pub frame: u32,
+    pub mouse_buttons: u32,
+    pub resolution: [f32; 2],
+    pub video_resolution: [f32; 2],
+    /// The size the effect chain renders at, which is independent of the window's.
+    pub render_size: [f32; 2],
+    pub mouse: [f32; 2],
+    pub face_center: [f32; 2],
+    pub left_hand: [f32; 2],
+    pub right_hand: [f32; 2],
+    pub face_present: u32,
+    pub pose_present: u32,
+    pub left_hand_present: u32,
+    pub right_hand_present: u32,
 }
 
 impl Uniforms {
-    pub fn new(width: f32, height: f32) -> Self {
-        Uniforms { width, height }
+    pub fn new(resolution: Vec2, video_resolution: Vec2) -> Self {
+        Uniforms {
+            resolution: resolution.to_array(),
+            video_resolution: video_resolution.to_array(),
+            ..Default::default()
+        }
+    }
+
+    /// Advances the clock and frame counter.
+    pub fn update_time(&mut self, time: f32, delta_time: f32) {
+        self.time = time;
+        self.delta_time = delta_time;
+        self.frame += 1;
+    }
+
+    /// Sets the mouse position and button state from the app's mouse, given the window size.
+    pub fn update_mouse(&mut self, mouse: &nannou::state::Mouse, window_size: Vec2) {
+        self.mouse = [
+            mouse.x / window_size.x + 0.5,
+            0.5 - mouse.y / window_size.y,
+        ];
+
+        let mut buttons = 0;
+        if mouse.buttons.left().is_down() {
+            buttons |= MOUSE_LEFT;
+        }
+        if mouse.buttons.right().is_down() {
+            buttons |= MOUSE_RIGHT;
+        }
+        if mouse.buttons.middle().is_down() {
+            buttons |= MOUSE_MIDDLE;
+        }
+        self.mouse_buttons = buttons;
+    }
+
+    /// Sets the detection summary from the detector's latest result.
+    /// Positions of missing detections keep their last value so effects can fade them out.
+    pub fn update_detections(&mut self, detector: &HolisticDetector) {
+        self.pose_present = detector.pose_present() as u32;
+
+        let face_center = detector.face_center();
+        self.face_present = face_center.is_some() as u32;
+        if let Some(center) = face_center {
+            self.face_center = center.to_array();
+        }
+
+        let left_hand = detector.left_hand_center();
+        self.left_hand_present = left_hand.is_some() as u32;
+        if let Some(center) = left_hand {
+            self.left_hand = center.to_array();
+        }
+
+        let right_hand = detector.right_hand_center();
+        self.right_hand_present = right_hand.is_some() as u32;
+        if let Some(center) = right_hand {
+            self.right_hand = center.to_array();
+        }
     }
 }
 
-fn as_bytes(uniforms: &Uniforms) -> &[u8] {
-    unsafe { wgpu::bytes::from(uniforms) }
+impl UniformBlock for Uniforms {
+    fn layout() -> UniformLayout {
+        UniformLayout::builder("Uniforms")
+            .field("time", UniformType::Float)
+            .field("delta_time", UniformType::Float)
+            .field("frame", UniformType::Uint)
+            .field("mouse_buttons", UniformType::Uint)
+            .field("resolution", UniformType::Vec2)
+            .field("video_resolution", UniformType::Vec2)
+            .field("render_size", UniformType::Vec2)
+            .field("mouse", UniformType::Vec2)
+            .field("face_center", UniformType::Vec2)
+            .field("left_hand", UniformType::Vec2)
+            .field("right_hand", UniformType::Vec2)
+            .field("face_present", UniformType::Uint)
+            .field("pose_present", UniformType::Uint)
+            .field("left_hand_present", UniformType::Uint)
+            .field("right_hand_present", UniformType::Uint)
+            .build()
+    }
+
+    fn write(&self, writer: &mut Std140Writer) {
+        writer
+            .write(self.time)
+            .write(self.delta_time)
+            .write(self.frame)
+            .write(self.mouse_buttons)
+            .write(self.resolution)
+            .write(self.video_resolution)
+            .write(self.render_size)
+            .write(self.mouse)
+            .write(self.face_center)
+            .write(self.left_hand)
+            .write(self.right_hand)
+            .write(self.face_present)
+            .write(self.pose_present)
+            .write(self.left_hand_present)
+            .write(self.right_hand_present);
+    }
 }
 
-pub struct UniformBuffer {
-    pub data: Uniforms,
-    pub buffer: wgpu::Buffer,
+/// The contents of the `uniforms.glsl` include, generated from the Rust definition of `Uniforms`.
+/// Define UNIFORMS_BINDING before including it if the block is not at binding 3.
+pub fn glsl_include() -> String {
+    let mut glsl = String::from(
+        "// Generated from uniforms::Uniforms, do not edit.
+
+#ifndef UNIFORMS_GLSL
+#define UNIFORMS_GLSL
+
+#ifndef UNIFORMS_BINDING
+#define UNIFORMS_BINDING 3
+#endif
+",
+    );
+
+    glsl.push_str(&format!(
+        "
+#define MOUSE_LEFT {}u
+#define MOUSE_RIGHT {}u
+#define MOUSE_MIDDLE {}u
+
+",
+        MOUSE_LEFT, MOUSE_RIGHT, MOUSE_MIDDLE
+    ));
+    glsl.push_str(&Uniforms::layout().glsl_block(0, "UNIFORMS_BINDING"));
+    glsl.push_str("\n#endif\n");
+    glsl
 }
 
-impl UniformBuffer {
-    pub fn new(device: &wgpu::Device, width: f32, height: f32) -> Self {
-        let data = Uniforms::new(width, height);
+/// A single uniform block in its own buffer.
+/// `data` can be changed freely, `update` only uploads it when its bytes have changed.
+pub struct UniformBuffer<T: UniformBlock> {
+    pub data: T,
+    pub buffer: wgpu::Buffer,
+    uploaded: Vec<u8>,
+}
 
-        let uniforms_bytes = as_bytes(&data);
+impl<T: UniformBlock> UniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, data: T) -> Self {
+        let uniforms_bytes = data.std140_bytes();
         let usage = wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST;
         let buffer = device.create_buffer_init(&wgpu::BufferInitDescriptor {
             label: Some("uniform-buffer"),
-            contents: uniforms_bytes,
+            contents: &uniforms_bytes,
             usage,
         });
 
-        Self { data, buffer }
+        Self {
+            data,
+            buffer,
+            uploaded: uniforms_bytes,
+        }
     }
 
-    pub fn update(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
-        // An update for the uniform buffer with the current time.
-        let uniforms_bytes = as_bytes(&self.data);
-        let uniforms_size = uniforms_bytes.len();
-        let usage = wgpu::BufferUsages::COPY_SRC;
-        let new_uniform_buffer = device.create_buffer_init(&wgpu::BufferInitDescriptor {
-            label: None,
-            contents: uniforms_bytes,
-            usage,
+    pub fn binding(&self) -> wgpu::BufferBinding {
+        wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: None,
+        }
+    }
+
+    /// Writes the data to the buffer if it changed since the last upload.
+    /// Returns true if anything was written.
+    pub fn update(&mut self, queue: &wgpu::Queue) -> bool {
+        let uniforms_bytes = self.data.std140_bytes();
+        if uniforms_bytes == self.uploaded {
+            return false;
+        }
+
+        queue.write_buffer(&self.buffer, 0, &uniforms_bytes);
+        self.uploaded = uniforms_bytes;
+        true
+    }
+}
+
+/// Identifies a block allocated in a `UniformArena`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UniformSlot(usize);
+
+struct SlotData {
+    offset: u64,
+    size: u64,
+    bytes: Vec<u8>,
+    dirty: bool,
+}
+
+/// Many uniform blocks, possibly of different types, packed into a single buffer.
+/// Used for per-pass uniforms so a chain of effects can be updated with a few writes
+/// rather than one buffer per pass. Blocks are only uploaded when their bytes change,
+/// and neighbouring dirty blocks are uploaded together.
+pub struct UniformArena {
+    pub buffer: wgpu::Buffer,
+    alignment: u64,
+    capacity: u64,
+    end: u64,
+    slots: Vec<SlotData>,
+}
+
+impl UniformArena {
+    pub fn new(device: &wgpu::Device, capacity: u64) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("uniform-arena"),
+            size: capacity,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
 
-        encoder.copy_buffer_to_buffer(
-            &new_uniform_buffer,
-            0,
-            &self.buffer,
-            0,
-            uniforms_size as u64,
-        );
+        Self {
+            buffer,
+            alignment: device.limits().min_uniform_buffer_offset_alignment as u64,
+            capacity,
+            end: 0,
+            slots: vec![],
+        }
+    }
+
+    /// Reserves space for a block with the given layout.
+    /// Panics if the arena is full.
+    pub fn allocate(&mut self, layout: &UniformLayout) -> UniformSlot {
+        let offset = (self.end + self.alignment - 1) / self.alignment * self.alignment;
+        let size = layout.size as u64;
+
+        if offset + size > self.capacity {
+            panic!(
+                "uniform arena of {} bytes is too small for block {}",
+                self.capacity, layout.name
+            );
+        }
+
+        self.end = offset + size;
+        self.slots.push(SlotData {
+            offset,
+            size,
+            bytes: vec![0; layout.size],
+            dirty: true,
+        });
+
+        UniformSlot(self.slots.len() - 1)
+    }
+
+    /// Stores a block's data, marking it dirty if it differs from what is already there.
+    pub fn set<T: UniformBlock>(&mut self, slot: UniformSlot, data: &T) {
+        let bytes = data.std140_bytes();
+        let slot_data = &mut self.slots[slot.0];
+
+        if bytes.len() as u64 != slot_data.size {
+            panic!(
+                "uniform slot {} holds {} bytes but was given {}",
+                slot.0,
+                slot_data.size,
+                bytes.len()
+            );
+        }
+
+        if bytes != slot_data.bytes {
+            slot_data.bytes = bytes;
+            slot_data.dirty = true;
+        }
+    }
+
+    pub fn binding(&self, slot: UniformSlot) -> wgpu::BufferBinding {
+        let slot_data = &self.slots[slot.0];
+        wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: slot_data.offset,
+            size: wgpu::BufferSize::new(slot_data.size),
+        }
+    }
+
+    /// Uploads every dirty block, merging runs of consecutive dirty blocks into one write.
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        let mut i = 0;
+
+        while i < self.slots.len() {
+            if !self.slots[i].dirty {
+                i += 1;
+                continue;
+            }
+
+            let start = self.slots[i].offset;
+            let mut bytes: Vec<u8> = vec![];
+
+            while i < self.slots.len() && self.slots[i].dirty {
+                let slot_data = &mut self.slots[i];
+                // fill the alignment padding between blocks
+                bytes.resize((slot_data.offset - start) as usize, 0);
+                bytes.extend_from_slice(&slot_data.bytes);
+                slot_data.dirty = false;
+                i += 1;
+            }
+
+            queue.write_buffer(&self.buffer, start, &bytes);
+        }
     }
 }
@@ -1,29 +1,525 @@
+use std::cell::RefCell;
+use std::fmt;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use nannou::prelude::*;
+use regex::Regex;
 
-/// Compiles a shader from the shaders directory
+use crate::compositor;
+use crate::effect_chain::{EffectPassDescriptor, EffectPassKind};
+use crate::effects;
+use crate::isf::{self, IsfEffect};
+use crate::landmarks;
+use crate::reflect::{self, EffectShader};
+use crate::shader_cache::{Dependency, ShaderCache};
+use crate::uniforms;
+
+/// The directory, relative to the shaders directory, that shared include files live in
+pub const SHADER_LIBRARY_DIR: &str = "lib";
+
+/// The directory shaders are loaded from
+pub fn shaders_path(app: &App) -> PathBuf {
+    app.project_path().unwrap().join("src").join("shaders")
+}
+
+/// A shader compilation failure, with the location of the first error when shaderc reports one
+#[derive(Debug)]
+pub struct ShaderError {
+    pub file: String,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+impl ShaderError {
+    pub fn new(file: &str, message: String) -> Self {
+        Self {
+            file: file.to_string(),
+            line: None,
+            message,
+        }
+    }
+
+    /// Parses shaderc diagnostics of the form `file:line: error: message`.
+    fn from_shaderc(file: &str, error: shaderc::Error) -> Self {
+        let log = match error {
+            shaderc::Error::CompilationError(_, log) => log,
+            e => return Self::new(file, e.to_string()),
+        };
+
+        let diagnostic = Regex::new(r"(?m)^(.+?):(\d+): error: (.*)$").unwrap();
+
+        match diagnostic.captures(&log) {
+            Some(captures) => Self {
+                file: captures[1].to_string(),
+                line: captures[2].parse().ok(),
+                message: captures[3].trim().to_string(),
+            },
+            None => Self::new(file, log.trim().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Built-in fragment shaders that can stand in for an effect that failed to compile
+pub enum FallbackShader {
+    /// Fills the target with magenta so broken effects are easy to spot.
+    Error,
+    /// Samples the previous pass's output.
+    Passthrough,
+}
+
+const ERROR_FRAG: &str = "#version 450
+
+layout(location = 0) in vec2 tex_coords;
+layout(location = 0) out vec4 frag_color;
+
+void main() {
+    frag_color = vec4(1.0, 0.0, 1.0, 1.0);
+}
+";
+
+const PASSTHROUGH_FRAG: &str = "#version 450
+
+layout(location = 0) in vec2 tex_coords;
+layout(location = 0) out vec4 frag_color;
+
+layout(set = 0, binding = 1) uniform texture2D tex;
+layout(set = 0, binding = 3) uniform sampler tex_sampler;
+
+void main() {
+    frag_color = vec4(texture(sampler2D(tex, tex_sampler), tex_coords).rgb, 1.0);
+}
+";
+
+/// Whether a shader is written in WGSL rather than GLSL, from its file extension
+pub fn is_wgsl(filename: &str) -> bool {
+    Path::new(filename).extension().and_then(|e| e.to_str()) == Some("wgsl")
+}
+
+/// Whether a shader is an ISF or Shadertoy effect to import, from its file extension
+pub fn is_isf(filename: &str) -> bool {
+    Path::new(filename).extension().and_then(|e| e.to_str()) == Some(isf::EXTENSION)
+}
+
+/// The kind of a GLSL shader, from its file extension
+pub fn shader_kind(filename: &str) -> Option<shaderc::ShaderKind> {
+    match Path::new(filename).extension()?.to_str()? {
+        "vert" => Some(shaderc::ShaderKind::Vertex),
+        "frag" => Some(shaderc::ShaderKind::Fragment),
+        "comp" => Some(shaderc::ShaderKind::Compute),
+        _ => None,
+    }
+}
+
+/// Compiles a shader from the shaders directory. WGSL shaders are handed to wgpu as is,
+/// and `kind` only applies to GLSL.
 pub fn compile_shader(
     app: &App,
     device: &wgpu::Device,
     filename: &str,
     kind: shaderc::ShaderKind,
-) -> wgpu::ShaderModule {
-    let path = app
-        .project_path()
+) -> Result<wgpu::ShaderModule, ShaderError> {
+    if is_wgsl(filename) {
+        let source = read_shader(app, filename)?;
+        reflect::reflect_wgsl(&source).map_err(|e| ShaderError::new(filename, e.to_string()))?;
+        return Ok(reflect::create_wgsl_module(device, filename, &source));
+    }
+
+    compile_shader_with_defines(app, device, filename, kind, &[])
+}
+
+/// Compiles a shader from the shaders directory with a set of macro definitions.
+pub fn compile_shader_with_defines(
+    app: &App,
+    device: &wgpu::Device,
+    filename: &str,
+    kind: shaderc::ShaderKind,
+    defines: &[(&str, Option<&str>)],
+) -> Result<wgpu::ShaderModule, ShaderError> {
+    let spirv = compile_spirv(app, filename, kind, defines)?;
+    Ok(wgpu::shader_from_spirv_bytes(device, &spirv))
+}
+
+/// Compiles an effect shader for a `kind` pass from the shaders directory and reflects the
+/// bindings it declares. GLSL shaders are compiled according to their extension (`.frag` or
+/// `.comp`), which must match `kind`, and WGSL shaders (`.wgsl`) must have a `main` entry point
+/// for its stage. ISF and Shadertoy effects (`.fs`) are fragment shaders, imported if they have a
+/// single pass; use `load_isf_effect` for multi-pass ones. `defines` only apply to GLSL; record
+/// them on the pass with `EffectPassDescriptor::defines`.
+pub fn compile_effect_shader(
+    app: &App,
+    device: &wgpu::Device,
+    filename: &str,
+    kind: EffectPassKind,
+    defines: &[(&str, Option<&str>)],
+) -> Result<EffectShader, ShaderError> {
+    if !defines.is_empty() && (is_wgsl(filename) || is_isf(filename)) {
+        return Err(ShaderError::new(
+            filename,
+            "macro definitions are only supported for GLSL shaders".into(),
+        ));
+    }
+
+    if is_wgsl(filename) {
+        let source = read_shader(app, filename)?;
+        return EffectShader::from_wgsl(device, filename, &source, kind.stage())
+            .map_err(|e| ShaderError::new(filename, e.to_string()));
+    }
+
+    if is_isf(filename) {
+        check_stage(filename, kind, naga::ShaderStage::Fragment)?;
+
+        let (effect, mut passes) = load_isf_effect(app, device, filename)?;
+        if effect.passes.len() != 1 {
+            return Err(ShaderError::new(
+                filename,
+                format!("has {} passes, expected 1", effect.passes.len()),
+            ));
+        }
+        return Ok(passes.remove(0).shader);
+    }
+
+    let glsl_kind = shader_kind(filename)
+        .ok_or_else(|| ShaderError::new(filename, "unknown shader extension".into()))?;
+    let stage = match glsl_kind {
+        shaderc::ShaderKind::Fragment => naga::ShaderStage::Fragment,
+        shaderc::ShaderKind::Compute => naga::ShaderStage::Compute,
+        _ => naga::ShaderStage::Vertex,
+    };
+    check_stage(filename, kind, stage)?;
+
+    let spirv = compile_spirv(app, filename, glsl_kind, defines)?;
+    EffectShader::from_spirv(device, &spirv).map_err(|e| ShaderError::new(filename, e.to_string()))
+}
+
+fn check_stage(
+    filename: &str,
+    kind: EffectPassKind,
+    found: naga::ShaderStage,
+) -> Result<(), ShaderError> {
+    if found == kind.stage() {
+        return Ok(());
+    }
+
+    let error = reflect::ReflectError::StageMismatch {
+        expected: kind.stage(),
+        found,
+    };
+    Err(ShaderError::new(filename, error.to_string()))
+}
+
+/// Borrows owned macro definitions, e.g. an effect pass's, to compile with.
+pub fn borrow_defines(defines: &[(String, Option<String>)]) -> Vec<(&str, Option<&str>)> {
+    defines
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_deref()))
+        .collect()
+}
+
+/// Imports an ISF or Shadertoy effect from the shaders directory as effect chain passes, one per
+/// ISF pass. If the effect `has_parameters`, bind an `isf::IsfParameters` for it to the chain
+/// under `isf::BLOCK_NAME`.
+pub fn load_isf_effect(
+    app: &App,
+    device: &wgpu::Device,
+    filename: &str,
+) -> Result<(IsfEffect, Vec<EffectPassDescriptor>), ShaderError> {
+    let source = read_shader(app, filename)?;
+    let effect =
+        IsfEffect::parse(&source).map_err(|e| ShaderError::new(filename, e.to_string()))?;
+
+    let shaders_dir = shaders_path(app);
+    let library_dir = shaders_dir.join(SHADER_LIBRARY_DIR);
+    let cache = ShaderCache::new(shader_cache_path(app));
+
+    let mut passes = vec![];
+    for index in 0..effect.passes.len() {
+        let spirv = compile_glsl(
+            &shaders_dir,
+            &library_dir,
+            Some(&cache),
+            filename,
+            &effect.pass_glsl(index),
+            shaderc::ShaderKind::Fragment,
+            &[],
+        )?;
+        let shader = EffectShader::from_spirv(device, &spirv)
+            .map_err(|e| ShaderError::new(filename, e.to_string()))?;
+
+        passes.push(
+            EffectPassDescriptor::new(filename, shader)
+                .history_frames(effect.history_frames(index)),
+        );
+    }
+
+    Ok((effect, passes))
+}
+
+fn read_shader(app: &App, filename: &str) -> Result<String, ShaderError> {
+    let path = shaders_path(app).join(filename);
+    fs::read_to_string(path).map_err(|e| ShaderError::new(filename, e.to_string()))
+}
+
+/// Compiles a shader from the shaders directory to SPIR-V.
+/// `#include "file.glsl"` is resolved relative to the including file and then in the shader library.
+pub fn compile_spirv(
+    app: &App,
+    filename: &str,
+    kind: shaderc::ShaderKind,
+    defines: &[(&str, Option<&str>)],
+) -> Result<Vec<u8>, ShaderError> {
+    let shaders_dir = shaders_path(app);
+    let cache = ShaderCache::new(shader_cache_path(app));
+    let library_dir = shaders_dir.join(SHADER_LIBRARY_DIR);
+    compile_spirv_in(
+        &shaders_dir,
+        &library_dir,
+        Some(&cache),
+        filename,
+        kind,
+        defines,
+    )
+}
+
+/// Compiles a shader in `shaders_dir` to SPIR-V without needing an `App`, resolving includes
+/// relative to the including file and then in `library_dir`.
+pub fn compile_spirv_in(
+    shaders_dir: &Path,
+    library_dir: &Path,
+    cache: Option<&ShaderCache>,
+    filename: &str,
+    kind: shaderc::ShaderKind,
+    defines: &[(&str, Option<&str>)],
+) -> Result<Vec<u8>, ShaderError> {
+    let path = shaders_dir.join(filename);
+    let code = fs::read_to_string(path).map_err(|e| ShaderError::new(filename, e.to_string()))?;
+    compile_glsl(
+        shaders_dir,
+        library_dir,
+        cache,
+        filename,
+        &code,
+        kind,
+        defines,
+    )
+}
+
+// The options every GLSL shader is compiled with besides its macro definitions. They're part of
+// the shader cache key, so changing them recompiles cached shaders.
+const OPTIMIZATION_LEVEL: shaderc::OptimizationLevel = shaderc::OptimizationLevel::Zero;
+const TARGET_ENV: shaderc::TargetEnv = shaderc::TargetEnv::Vulkan;
+const TARGET_ENV_VERSION: shaderc::EnvVersion = shaderc::EnvVersion::Vulkan1_0;
+
+fn compile_options_key() -> String {
+    format!(
+        "{:?} {:?} {:?}",
+        OPTIMIZATION_LEVEL, TARGET_ENV, TARGET_ENV_VERSION
+    )
+}
+
+/// Compiles GLSL source to SPIR-V as if it was the contents of `filename` in `shaders_dir`,
+/// e.g. for shaders generated from other formats.
+pub fn compile_glsl(
+    shaders_dir: &Path,
+    library_dir: &Path,
+    cache: Option<&ShaderCache>,
+    filename: &str,
+    code: &str,
+    kind: shaderc::ShaderKind,
+    defines: &[(&str, Option<&str>)],
+) -> Result<Vec<u8>, ShaderError> {
+    let key = ShaderCache::key(filename, code, kind, &compile_options_key(), defines);
+    let cached = cache.and_then(|cache| {
+        cache.load(key, |dependency| {
+            resolve_include(
+                shaders_dir,
+                library_dir,
+                &dependency.requested,
+                dependency.include_type(),
+                &dependency.requesting,
+            )
+            .ok()
+            .map(|resolved| resolved.content)
+        })
+    });
+    if let Some(spirv) = cached {
+        return Ok(spirv);
+    }
+
+    let mut compiler = shaderc::Compiler::new()
+        .ok_or_else(|| ShaderError::new(filename, "unable to create shaderc compiler".into()))?;
+    let mut options = shaderc::CompileOptions::new()
+        .ok_or_else(|| ShaderError::new(filename, "unable to create shaderc options".into()))?;
+
+    options.set_optimization_level(OPTIMIZATION_LEVEL);
+    options.set_target_env(TARGET_ENV, TARGET_ENV_VERSION as u32);
+    for (name, value) in defines.iter() {
+        options.add_macro_definition(name, *value);
+    }
+
+    // record every include so the cache entry can be invalidated when one changes
+    let dependencies = Rc::new(RefCell::new(vec![]));
+    let recorded = dependencies.clone();
+    let (include_dir, library_dir) = (shaders_dir.to_path_buf(), library_dir.to_path_buf());
+    options.set_include_callback(move |requested, include_type, requesting, _depth| {
+        let result = resolve_include(
+            &include_dir,
+            &library_dir,
+            requested,
+            include_type,
+            requesting,
+        );
+        if let Ok(resolved) = &result {
+            recorded.borrow_mut().push(Dependency::new(
+                requested,
+                include_type,
+                requesting,
+                &resolved.content,
+            ));
+        }
+        result
+    });
+
+    let spirv = compiler
+        .compile_into_spirv(code, kind, filename, "main", Some(&options))
+        .map_err(|e| ShaderError::from_shaderc(filename, e))?;
+
+    let spirv = spirv.as_binary_u8().to_vec();
+    if let Some(cache) = cache {
+        cache.store(key, &spirv, &dependencies.borrow());
+    }
+    Ok(spirv)
+}
+
+/// The directory compiled shaders are cached in
+pub fn shader_cache_path(app: &App) -> PathBuf {
+    app.project_path()
         .unwrap()
-        .join("src")
-        .join("shaders")
-        .join(filename)
-        .into_os_string()
-        .into_string()
-        .unwrap();
-    let code = fs::read_to_string(path).unwrap();
-    let mut compiler = shaderc::Compiler::new().unwrap();
+        .join("target")
+        .join("shader-cache")
+}
+
+/// Includes generated from Rust definitions, so shaders always agree with the data uploaded to them
+fn generated_include(requested: &str) -> Option<String> {
+    match requested {
+        uniforms::GLSL_INCLUDE_NAME => Some(uniforms::glsl_include()),
+        landmarks::GLSL_INCLUDE_NAME => Some(landmarks::glsl_include()),
+        compositor::GLSL_INCLUDE_NAME => Some(compositor::glsl_include()),
+        _ => effects::glsl_include(requested),
+    }
+}
+
+fn resolve_include(
+    shaders_dir: &Path,
+    library_dir: &Path,
+    requested: &str,
+    include_type: shaderc::IncludeType,
+    requesting: &str,
+) -> shaderc::IncludeCallbackResult {
+    if let Some(content) = generated_include(requested) {
+        return Ok(shaderc::ResolvedInclude {
+            resolved_name: requested.to_string(),
+            content,
+        });
+    }
+
+    let mut candidates = vec![];
+
+    if let shaderc::IncludeType::Relative = include_type {
+        let requesting_dir = Path::new(requesting).parent().unwrap_or_else(|| Path::new(""));
+        candidates.push(shaders_dir.join(requesting_dir).join(requested));
+    }
+
+    candidates.push(library_dir.join(requested));
+
+    for candidate in candidates {
+        if let Ok(content) = fs::read_to_string(&candidate) {
+            return Ok(shaderc::ResolvedInclude {
+                resolved_name: candidate.to_string_lossy().into_owned(),
+                content,
+            });
+        }
+    }
+
+    Err(format!("unable to find include file {}", requested))
+}
+
+/// Loads an effect from the shaders directory as effect chain passes: one fragment pass, or the
+/// passes of an imported ISF or Shadertoy effect along with its inputs block if it has one.
+pub fn load_effect(
+    app: &App,
+    device: &wgpu::Device,
+    filename: &str,
+) -> Result<(Vec<EffectPassDescriptor>, Option<isf::IsfParameters>), ShaderError> {
+    if !is_isf(filename) {
+        let shader = compile_effect_shader(app, device, filename, EffectPassKind::Fragment, &[])?;
+        return Ok((vec![EffectPassDescriptor::new(filename, shader)], None));
+    }
+
+    let (effect, passes) = load_isf_effect(app, device, filename)?;
+    let parameters = effect
+        .has_parameters()
+        .then(|| isf::IsfParameters::new(device, &effect));
+    Ok((passes, parameters))
+}
+
+/// Loads an effect like `load_effect`, substituting a single fallback pass if it fails.
+/// The fallback is named after the effect, so it's replaced once the effect reloads.
+pub fn load_effect_or_fallback(
+    app: &App,
+    device: &wgpu::Device,
+    filename: &str,
+    fallback: FallbackShader,
+) -> (Vec<EffectPassDescriptor>, Option<isf::IsfParameters>) {
+    match load_effect(app, device, filename) {
+        Ok(effect) => effect,
+        Err(e) => {
+            println!("Error compiling shader, using fallback: {}", e);
+            let shader = compile_fallback_shader(device, fallback);
+            (vec![EffectPassDescriptor::new(filename, shader)], None)
+        }
+    }
+}
+
+/// Compiles one of the built-in fallback fragment shaders
+pub fn compile_fallback_shader(device: &wgpu::Device, fallback: FallbackShader) -> EffectShader {
+    let spirv = compile_fallback_spirv(&fallback).unwrap();
+    EffectShader::from_spirv(device, &spirv).unwrap()
+}
+
+/// The file name a fallback shader is reported under
+pub fn fallback_name(fallback: &FallbackShader) -> &'static str {
+    match fallback {
+        FallbackShader::Error => "error.frag",
+        FallbackShader::Passthrough => "passthrough.frag",
+    }
+}
+
+pub fn compile_fallback_spirv(fallback: &FallbackShader) -> Result<Vec<u8>, ShaderError> {
+    let filename = fallback_name(fallback);
+    let code = match fallback {
+        FallbackShader::Error => ERROR_FRAG,
+        FallbackShader::Passthrough => PASSTHROUGH_FRAG,
+    };
+
+    let mut compiler = shaderc::Compiler::new()
+        .ok_or_else(|| ShaderError::new(filename, "unable to create shaderc compiler".into()))?;
     let spirv = compiler
-        .compile_into_spirv(code.as_str(), kind, filename, "main", None)
-        .unwrap();
-    wgpu::shader_from_spirv_bytes(device, spirv.as_binary_u8())
+        .compile_into_spirv(code, shaderc::ShaderKind::Fragment, filename, "main", None)
+        .map_err(|e| ShaderError::from_shaderc(filename, e))?;
+    Ok(spirv.as_binary_u8().to_vec())
 }
 
 pub fn map(input: f32, in_min: f32, in_max: f32, out_min: f32, out_max: f32) -> f32 {
@@ -41,6 +537,18 @@ pub fn floats_as_byte_vec(data: &[f32]) -> Vec<u8> {
     bytes
 }
 
+/// The average position of a set of landmarks
+pub fn landmarks_center(landmarks: &[mediapipe::Landmark]) -> Vec2 {
+    if landmarks.is_empty() {
+        return Vec2::ZERO;
+    }
+
+    let sum = landmarks
+        .iter()
+        .fold(Vec2::ZERO, |acc, l| acc + Vec2::new(l.x, l.y));
+    sum / landmarks.len() as f32
+}
+
 pub fn draw_landmarks<C: nannou::color::IntoLinSrgba<f32> + std::marker::Copy>(
     draw: &Draw, 
     landmarks: &Vec<mediapipe::Landmark>,
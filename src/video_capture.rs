@@ -3,7 +3,7 @@ use opencv::prelude::*;
 use ringbuf::{Consumer, RingBuffer};
 use std::fmt;
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 use std::{thread};
 
 use crate::texture;
@@ -17,8 +17,16 @@ enum Message {
     Unpause(()),
 }
 
+/// Identifies a captured frame, so results computed from it can be matched back to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameTag {
+    /// Counts every frame read from the capture, including ones dropped before display.
+    pub sequence: u64,
+    pub captured: Instant,
+}
+
 pub struct VideoConsumer {
-    consumer: Consumer<opencv::core::Mat>,
+    consumer: Consumer<(FrameTag, opencv::core::Mat)>,
 }
 
 impl fmt::Debug for VideoConsumer {
@@ -31,6 +39,7 @@ impl fmt::Debug for VideoConsumer {
 pub struct VideoCapture {
     pub error: Option<String>,
     pub frame: Option<opencv::core::Mat>,
+    pub frame_tag: Option<FrameTag>,
     pub running: bool,
     pub speed: f32,
     pub video_size: Vec2,
@@ -69,7 +78,7 @@ impl VideoCapture {
         );
 
         // setup ring buffer
-        let video_ring_buffer = RingBuffer::<opencv::core::Mat>::new(2);
+        let video_ring_buffer = RingBuffer::<(FrameTag, opencv::core::Mat)>::new(2);
         let (mut video_producer, video_consumer) = video_ring_buffer.split();
 
         // setup communication channels
@@ -80,6 +89,7 @@ impl VideoCapture {
         let capture_thread = thread::spawn(move || {
             let clock = SystemTime::now();
             let mut video_speed = speed as f64;
+            let mut sequence = 0;
 
             let mut frame = unsafe {
                 opencv::core::Mat::new_rows_cols(
@@ -110,7 +120,13 @@ impl VideoCapture {
                     }
                 }
 
-                video_producer.push(frame.clone()).ok();
+                let tag = FrameTag {
+                    sequence,
+                    captured: Instant::now(),
+                };
+                sequence += 1;
+
+                video_producer.push((tag, frame.clone())).ok();
 
                 if let Ok(msg) = message_channel_rx.try_recv() {
                     match msg {
@@ -152,6 +168,7 @@ impl VideoCapture {
             error: None,
             error_channel_rx,
             frame: None,
+            frame_tag: None,
             running: true,
             speed,
             video_consumer: VideoConsumer {
@@ -189,7 +206,16 @@ impl VideoCapture {
             return;
         }
 
-        self.frame = self.video_consumer.consumer.pop();
+        match self.video_consumer.consumer.pop() {
+            Some((tag, frame)) => {
+                self.frame = Some(frame);
+                self.frame_tag = Some(tag);
+            }
+            None => {
+                self.frame = None;
+                self.frame_tag = None;
+            }
+        }
     }
 
     pub fn update_texture(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
@@ -202,10 +228,21 @@ impl VideoCapture {
             None => return,
         };
 
+        // println!("uploading texture");
+        self.upload_frame(device, encoder, frame);
+    }
+
+    /// Uploads `frame` to the video texture in place of the current frame, e.g. an older frame
+    /// kept in a `FrameHistory`.
+    pub fn upload_frame(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &opencv::core::Mat,
+    ) {
         let width = self.video_size.x as u32;
         let height = self.video_size.y as u32;
 
-        // println!("uploading texture");
         texture::upload_mat_rgb(device, encoder, frame, &self.video_texture, width, height);
     }
 
@@ -1,7 +1,7 @@
 use nannou::prelude::*;
 use opencv::prelude::*;
 
-use crate::video_capture::VideoCapture;
+use crate::video_capture::{FrameTag, VideoCapture};
 
 pub struct WebcamCapture {
     pub updated: bool,
@@ -57,6 +57,17 @@ impl WebcamCapture {
         }
     }
 
+    pub fn upload_frame(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &Mat,
+    ) {
+        if let Some(video_capture) = &self.video_capture {
+            video_capture.upload_frame(device, encoder, frame);
+        }
+    }
+
     pub fn start_texture_upload(&self) {
         if let Some(video_capture) = &self.video_capture {
             video_capture.start_texture_upload();
@@ -84,4 +95,8 @@ impl WebcamCapture {
     pub fn get_frame_ref(&self) -> Option<&Mat> {
         self.video_capture.as_ref().unwrap().frame.as_ref()
     }
+
+    pub fn get_frame_tag(&self) -> Option<FrameTag> {
+        self.video_capture.as_ref().unwrap().frame_tag
+    }
 }